@@ -25,5 +25,8 @@ EXIT_CODE=$?
 log "Hybrid Mount exited with code $EXIT_CODE"
 if [ "$EXIT_CODE" = "0" ]; then
     /data/adb/ksud kernel notify-module-mounted
+    log "Starting boot watcher..."
+    "$BINARY" boot-watch >> "$LOG_FILE" 2>&1 &
+    "$BINARY" daemon >> "$LOG_FILE" 2>&1 &
 fi
 exit $EXIT_CODE
@@ -5,9 +5,10 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 
-use super::config::CONFIG_FILE_DEFAULT;
+use super::{config::CONFIG_FILE_DEFAULT, schema::SchemaTarget};
+use crate::selinux::FileKind;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "meta-hybrid", version, about = "Hybrid Mount Metamodule")]
 pub struct Cli {
     #[arg(short = 'c', long = "config")]
@@ -24,13 +25,31 @@ pub struct Cli {
     pub command: Option<Commands>,
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
     GenConfig {
         #[arg(short = 'o', long = "output", default_value = CONFIG_FILE_DEFAULT)]
         output: PathBuf,
     },
-    ShowConfig,
+    ShowConfig {
+        #[arg(long)]
+        origins: bool,
+    },
+    #[command(name = "migrate-config")]
+    MigrateConfig {
+        #[arg(long)]
+        dry_run: bool,
+    },
+    #[command(name = "validate-config")]
+    ValidateConfig,
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    Schema {
+        #[arg(value_enum)]
+        target: Option<SchemaTarget>,
+    },
     #[command(name = "save-config")]
     SaveConfig {
         #[arg(long)]
@@ -44,9 +63,37 @@ pub enum Commands {
         payload: String,
     },
     Storage,
+    #[command(name = "verify-sync")]
+    VerifySync,
     Modules,
     Conflicts,
     Diagnostics,
+    Plan {
+        #[arg(long)]
+        tree: bool,
+    },
+    Explain {
+        path: PathBuf,
+    },
+    #[command(name = "boot-watch")]
+    BootWatch {
+        #[arg(long)]
+        timeout: Option<u64>,
+        #[arg(long)]
+        prop_file: Option<PathBuf>,
+    },
+    Daemon,
+    Rpc,
+    Rescue {
+        #[arg(long)]
+        abort: bool,
+    },
+    #[command(name = "selinux-label")]
+    SelinuxLabel {
+        path: PathBuf,
+        #[arg(long, value_enum)]
+        kind: Option<FileKind>,
+    },
     #[command(name = "system-action")]
     SystemAction {
         #[arg(long)]
@@ -63,7 +110,14 @@ pub enum Commands {
     },
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigAction {
+    Get { key: String },
+    Set { key: String, value: String },
+    Unset { key: String },
+}
+
+#[derive(Subcommand, Debug, Clone)]
 pub enum PoaceaeAction {
     Hide {
         name: String,
@@ -1,64 +1,96 @@
 // Copyright 2026 Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{fs::File, path::Path};
+use std::{fs::File, path::Path, time::Duration};
 
 use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::{
     conf::{
-        cli::{Cli, PoaceaeAction},
+        cli::{Cli, ConfigAction, PoaceaeAction},
         config::{CONFIG_FILE_DEFAULT, Config},
+        dropin, edit, migrate,
+        schema::{self, SchemaTarget},
+        validate,
     },
-    core::{granary, inventory, modules, planner, poaceae, storage}, // Added poaceae
+    core::{
+        explain, granary, inventory, modules, planner, poaceae, rescue, state, storage, sync,
+    }, // Added poaceae
+    daemon,
+    props,
+    selinux::{self, FileKind},
+    utils,
 };
 
 #[derive(Serialize)]
-struct DiagnosticIssueJson {
+struct SelinuxLabelJson {
+    path: String,
+    kind: FileKind,
+    context: Option<String>,
+    stock_context: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DiagnosticIssueJson {
+    /// Info, Warning or Critical
     level: String,
     context: String,
     message: String,
 }
 
-fn load_config(cli: &Cli) -> Result<Config> {
-    if let Some(config_path) = &cli.config {
-        return Config::from_file(config_path).with_context(|| {
-            format!(
-                "Failed to load config from custom path: {}",
-                config_path.display()
-            )
-        });
-    }
+/// 与启动流程相同的生效配置：配置文件层、命中的 [[profile]] 与命令行覆盖
+pub fn load_config(cli: &Cli) -> Result<Config> {
+    let mut config = Config::load_effective(cli.config.as_deref())?;
+    apply_cli_overrides(&mut config, cli);
+    Ok(config)
+}
 
-    match Config::load_default() {
+/// 未指定 --config 时默认配置文件损坏则退回默认值（仍应用命令行覆盖），
+/// 供启动流程与 boot-watch 共用，两者必须看到同一份 boot_watch 设置
+pub fn load_config_or_default(cli: &Cli) -> Result<Config> {
+    match load_config(cli) {
         Ok(config) => Ok(config),
-        Err(e) => {
-            let is_not_found = e
-                .root_cause()
-                .downcast_ref::<std::io::Error>()
-                .map(|io_err| io_err.kind() == std::io::ErrorKind::NotFound)
-                .unwrap_or(false);
-
-            if is_not_found {
-                Ok(Config::default())
-            } else {
-                Err(e).context(format!(
-                    "Failed to load default config from {}",
-                    CONFIG_FILE_DEFAULT
-                ))
-            }
+        Err(e) if cli.config.is_none() => {
+            utils::log_early(
+                log::Level::Warn,
+                format!("Failed to load default config, using defaults: {:#}", e),
+            );
+            let mut config = Config::default();
+            apply_cli_overrides(&mut config, cli);
+            Ok(config)
         }
+        Err(e) => Err(e),
     }
 }
 
+pub fn apply_cli_overrides(config: &mut Config, cli: &Cli) {
+    config.merge_with_cli(
+        cli.moduledir.clone(),
+        cli.mountsource.clone(),
+        cli.verbose,
+        cli.partitions.clone(),
+    );
+}
+
 pub fn handle_gen_config(output: &Path) -> Result<()> {
     Config::default()
         .save_to_file(output)
         .with_context(|| format!("Failed to save generated config to {}", output.display()))
 }
 
-pub fn handle_show_config(cli: &Cli) -> Result<()> {
+#[derive(Serialize)]
+struct ConfigWithOrigins {
+    config: Config,
+    origins: dropin::Origins,
+}
+
+pub fn handle_show_config(cli: &Cli, origins: bool) -> Result<()> {
+    if origins {
+        return show_config_origins(cli);
+    }
+
     let config = load_config(cli)?;
 
     let json = serde_json::to_string(&config).context("Failed to serialize config to JSON")?;
@@ -68,25 +100,173 @@ pub fn handle_show_config(cli: &Cli) -> Result<()> {
     Ok(())
 }
 
-pub fn handle_save_config(cli: &Cli, payload: &str) -> Result<()> {
-    if let Ok(old_config) = load_config(cli)
-        && let Err(e) = granary::create_snapshot(&old_config, "Auto-Backup", "Pre-WebUI Save")
-    {
-        log::warn!("Failed to create Backup: {}", e);
+/// 输出生效配置及每个值的来源（文件、profile:<name> 或 command line），其余值标记为 default
+fn show_config_origins(cli: &Cli) -> Result<()> {
+    let (mut config, mut file_origins) =
+        Config::load_effective_with_origins(cli.config.as_deref())?;
+    apply_cli_overrides(&mut config, cli);
+
+    let overridden = [
+        ("moduledir", cli.moduledir.is_some()),
+        ("mountsource", cli.mountsource.is_some()),
+        ("verbose", cli.verbose),
+        ("partitions", !cli.partitions.is_empty()),
+    ];
+    for (key, set) in overridden {
+        if set {
+            file_origins.insert(key.to_string(), vec!["command line".to_string()]);
+        }
+    }
+
+    let effective = toml::Table::try_from(&config).context("Failed to serialize config")?;
+    let mut origins = dropin::Origins::new();
+    dropin::record_origins(&effective, "", "default", &mut origins);
+
+    for (key, sources) in origins.iter_mut() {
+        if let Some(found) = file_origins.get(key) {
+            *sources = found.clone();
+        }
     }
 
-    let json_bytes = (0..payload.len())
+    let json = serde_json::to_string(&ConfigWithOrigins { config, origins })
+        .context("Failed to serialize config to JSON")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_migrate_config(cli: &Cli, dry_run: bool) -> Result<()> {
+    let path = cli
+        .config
+        .as_deref()
+        .unwrap_or(Path::new(CONFIG_FILE_DEFAULT));
+
+    let report = if dry_run {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config from {}", path.display()))?;
+        migrate::parse(&content)?.1
+    } else {
+        migrate::upgrade_in_place(path)
+            .with_context(|| format!("Failed to migrate config {}", path.display()))?
+    };
+
+    let json = serde_json::to_string(&report).context("Failed to serialize migration report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+fn decode_hex_payload(payload: &str) -> Result<Vec<u8>> {
+    (0..payload.len())
         .step_by(2)
         .map(|i| u8::from_str_radix(&payload[i..i + 2], 16))
         .collect::<Result<Vec<u8>, _>>()
-        .context("Failed to decode hex payload")?;
+        .context("Failed to decode hex payload")
+}
+
+pub fn handle_validate_config(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let issues = validate::validate(&config);
+
+    let json = serde_json::to_string(&issues).context("Failed to serialize validation issues")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_config(cli: &Cli, action: &ConfigAction) -> Result<()> {
+    let config_path = cli
+        .config
+        .as_deref()
+        .unwrap_or(Path::new(CONFIG_FILE_DEFAULT));
+
+    match action {
+        ConfigAction::Get { key } => {
+            let config = load_config(cli)?;
+            let value = edit::get(&config, key)?;
+            let json = serde_json::to_string(&value).context("Failed to serialize config value")?;
+            println!("{}", json);
+        }
+        ConfigAction::Set { key, value } => {
+            let warnings = edit::set(config_path, key, value)?;
+            println!("Set {} in {}", key, config_path.display());
+            print_unrelated_issues(&warnings);
+        }
+        ConfigAction::Unset { key } => {
+            let warnings = edit::unset(config_path, key)?;
+            println!("Unset {} in {}", key, config_path.display());
+            print_unrelated_issues(&warnings);
+        }
+    }
+
+    Ok(())
+}
+
+/// 修改前已存在、与本次修改无关的严重问题，仅提示不阻止写入
+fn print_unrelated_issues(issues: &[validate::ValidationIssue]) {
+    for issue in issues {
+        println!("Warning: {}: {}", issue.field, issue.message);
+    }
+}
+
+pub fn handle_schema(target: Option<SchemaTarget>) -> Result<()> {
+    let json = match target {
+        Some(target) => serde_json::to_string_pretty(&schema::generate(target)),
+        None => serde_json::to_string_pretty(&schema::generate_all()),
+    }
+    .context("Failed to serialize schema")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+/// 保存前校验配置并为旧配置创建快照，存在严重问题时拒绝保存
+/// 提交的配置是基于 show-config 的生效配置编辑的，只有改动过的值写入配置文件，
+/// 删除 config.d 片段或 profile 后其覆盖的值仍会恢复
+pub fn save_config(cli: &Cli, config: &Config) -> Result<()> {
+    let critical: Vec<_> = validate::validate(config)
+        .into_iter()
+        .filter(|i| i.is_critical())
+        .collect();
+
+    if !critical.is_empty() {
+        bail!(
+            "Config failed validation: {}",
+            serde_json::to_string(&critical)?
+        );
+    }
+
+    let path = cli
+        .config
+        .as_deref()
+        .unwrap_or(Path::new(CONFIG_FILE_DEFAULT));
+    let shown = load_config(cli)?;
+
+    if let Err(e) = granary::create_snapshot(&shown, "Auto-Backup", "Pre-WebUI Save") {
+        log::warn!("Failed to create Backup: {}", e);
+    }
+
+    let base = if path.exists() {
+        Config::from_table(Config::read_table(path)?)?
+    } else {
+        Config::default()
+    };
+
+    edit::save_changes(path, &base, &shown, config).context("Failed to save config file")
+}
+
+pub fn handle_save_config(cli: &Cli, payload: &str) -> Result<()> {
+    let json_bytes = decode_hex_payload(payload)?;
 
     let config: Config =
         serde_json::from_slice(&json_bytes).context("Failed to parse config JSON payload")?;
 
-    config
-        .save_to_file(CONFIG_FILE_DEFAULT)
-        .context("Failed to save config file")?;
+    save_config(cli, &config)?;
 
     println!("Configuration saved successfully.");
 
@@ -94,14 +274,20 @@ pub fn handle_save_config(cli: &Cli, payload: &str) -> Result<()> {
 }
 
 pub fn handle_save_module_rules(module_id: &str, payload: &str) -> Result<()> {
-    let json_bytes = (0..payload.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&payload[i..i + 2], 16))
-        .collect::<Result<Vec<u8>, _>>()
-        .context("Failed to decode hex payload")?;
+    let json_bytes = decode_hex_payload(payload)?;
+
+    save_module_rules(module_id, &json_bytes)?;
+
+    println!("Module rules saved for {}", module_id);
+
+    Ok(())
+}
+
+pub fn save_module_rules(module_id: &str, json_bytes: &[u8]) -> Result<()> {
+    utils::validate_module_id(module_id)?;
 
     let _rules: inventory::ModuleRules =
-        serde_json::from_slice(&json_bytes).context("Failed to parse module rules JSON")?;
+        serde_json::from_slice(json_bytes).context("Failed to parse module rules JSON")?;
 
     let rules_dir = Path::new("/data/adb/meta-hybrid/rules");
 
@@ -119,8 +305,6 @@ pub fn handle_save_module_rules(module_id: &str, payload: &str) -> Result<()> {
     std::fs::write(&rule_file, json_bytes)
         .with_context(|| format!("Failed to write rule file to {}", rule_file.display()))?;
 
-    println!("Module rules saved for {}", module_id);
-
     Ok(())
 }
 
@@ -128,6 +312,28 @@ pub fn handle_storage() -> Result<()> {
     storage::print_status().context("Failed to retrieve storage status")
 }
 
+pub fn handle_verify_sync(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let modules = inventory::scan(&config.moduledir, &config)
+        .context("Failed to scan modules for sync verification")?;
+
+    // 优先使用上次启动实际使用的工作区
+    let mount_point = state::RuntimeState::load()
+        .ok()
+        .map(|s| s.mount_point)
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| config.hybrid_mnt_dir.clone().into());
+
+    let issues = sync::verify(&modules, &mount_point, &config);
+
+    let json = serde_json::to_string(&issues).context("Failed to serialize verification report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
 pub fn handle_modules(cli: &Cli) -> Result<()> {
     let config = load_config(cli)?;
 
@@ -137,32 +343,60 @@ pub fn handle_modules(cli: &Cli) -> Result<()> {
 pub fn handle_conflicts(cli: &Cli) -> Result<()> {
     let config = load_config(cli)?;
 
-    let module_list = inventory::scan(&config.moduledir, &config)
+    let conflicts = conflicts_report(&config)?;
+
+    let json = serde_json::to_string(&conflicts).context("Failed to serialize conflict report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn conflicts_report(config: &Config) -> Result<Vec<planner::ConflictEntry>> {
+    let module_list = inventory::scan(&config.moduledir, config)
         .context("Failed to scan modules for conflict analysis")?;
 
-    let plan = planner::generate(&config, &module_list, &config.moduledir)
+    let plan = planner::generate(config, &module_list, &config.moduledir)
         .context("Failed to generate plan for conflict analysis")?;
 
-    let report = plan.analyze();
+    Ok(plan.analyze().conflicts)
+}
+
+pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let json_issues = diagnostics_report(&config)?;
 
     let json =
-        serde_json::to_string(&report.conflicts).context("Failed to serialize conflict report")?;
+        serde_json::to_string(&json_issues).context("Failed to serialize diagnostics report")?;
 
     println!("{}", json);
 
     Ok(())
 }
 
-pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
-    let config = load_config(cli)?;
-
-    let module_list = inventory::scan(&config.moduledir, &config)
+pub fn diagnostics_report(config: &Config) -> Result<Vec<DiagnosticIssueJson>> {
+    let inventory = inventory::scan_inventory(&config.moduledir, config)
         .context("Failed to scan modules for diagnostics")?;
 
-    let plan = planner::generate(&config, &module_list, &config.moduledir)
+    let plan = planner::generate(config, &inventory.modules, &config.moduledir)
         .context("Failed to generate plan for diagnostics")?;
 
-    let report = plan.analyze();
+    let mut report = plan.analyze();
+    report.diagnostics.extend(inventory.diagnostics());
+
+    // 附加上次启动记录的问题（例如被隔离的 OverlayFS 故障模块），跳过与本次扫描重复的条目
+    if let Ok(runtime) = state::RuntimeState::load() {
+        for issue in runtime.diagnostics {
+            let duplicate = report
+                .diagnostics
+                .iter()
+                .any(|i| i.context == issue.context && i.message == issue.message);
+            if !duplicate {
+                report.diagnostics.push(issue);
+            }
+        }
+    }
 
     let json_issues: Vec<DiagnosticIssueJson> = report
         .diagnostics
@@ -178,8 +412,109 @@ pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
         })
         .collect();
 
-    let json =
-        serde_json::to_string(&json_issues).context("Failed to serialize diagnostics report")?;
+    Ok(json_issues)
+}
+
+/// 使用与启动相同的生效配置（config.d、profile 与命令行覆盖）生成挂载计划
+pub fn handle_plan(cli: &Cli, tree: bool) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let module_list = inventory::scan(&config.moduledir, &config)
+        .context("Failed to scan modules for mount plan")?;
+
+    let plan = planner::generate(&config, &module_list, &config.moduledir)
+        .context("Failed to generate mount plan")?;
+
+    if tree {
+        print!("{}", plan.render_tree());
+    } else {
+        let json = serde_json::to_string(&plan).context("Failed to serialize mount plan")?;
+
+        println!("{}", json);
+    }
+
+    Ok(())
+}
+
+pub fn handle_explain(cli: &Cli, path: &Path) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let explanation = explain::explain(&config, path)
+        .with_context(|| format!("Failed to explain {}", path.display()))?;
+
+    let json = serde_json::to_string(&explanation).context("Failed to serialize explanation")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_boot_watch(cli: &Cli, timeout: Option<u64>, prop_file: Option<&Path>) -> Result<()> {
+    let config = load_config_or_default(cli)?;
+
+    utils::init_logging(config.verbose).context("Failed to initialize logging")?;
+
+    if !config.boot_watch.enabled && timeout.is_none() && prop_file.is_none() {
+        log::info!("Boot Watch disabled in config, nothing to do.");
+        return Ok(());
+    }
+
+    let timeout = timeout.unwrap_or(config.boot_watch.timeout_secs);
+    let props = props::source_from(prop_file.or(config.boot_watch.prop_file.as_deref()));
+
+    granary::watch_boot_completed(
+        props.as_ref(),
+        Duration::from_secs(timeout),
+        Duration::from_secs(2),
+    );
+
+    Ok(())
+}
+
+pub fn handle_daemon(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+
+    utils::init_logging(config.verbose).context("Failed to initialize logging")?;
+
+    if !config.daemon.enabled {
+        log::info!("Daemon disabled in config, nothing to do.");
+        return Ok(());
+    }
+
+    daemon::run(cli)
+}
+
+pub fn handle_rpc() -> Result<()> {
+    daemon::forward_stdin()
+}
+
+pub fn handle_rescue(abort: bool) -> Result<()> {
+    if abort {
+        rescue::abort()?;
+        println!("Rescue session aborted.");
+        return Ok(());
+    }
+
+    let json = serde_json::to_string(&rescue::report())?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_selinux_label(path: &Path, kind: Option<FileKind>) -> Result<()> {
+    let kind = kind
+        .or_else(|| FileKind::of_path(path))
+        .unwrap_or(FileKind::Regular);
+
+    let label = SelinuxLabelJson {
+        path: path.to_string_lossy().to_string(),
+        kind,
+        context: selinux::lookup(path, kind),
+        stock_context: utils::lgetfilecon(path).ok(),
+    };
+
+    let json = serde_json::to_string(&label).context("Failed to serialize SELinux label")?;
 
     println!("{}", json);
 
@@ -7,15 +7,23 @@ use std::{
 };
 
 use anyhow::{Context, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 pub const CONFIG_FILE_DEFAULT: &str = "/data/adb/meta-hybrid/config.toml";
-use crate::defs::DEFAULT_HYBRID_MNT_DIR;
+use super::{
+    dropin::{self, CONFIG_DROPIN_DIR, Origins},
+    edit, migrate,
+    profile::{self, DeviceFacts, Profile},
+};
+use crate::{defs::DEFAULT_HYBRID_MNT_DIR, props, utils};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct BackupConfig {
+    /// Maximum number of snapshots to keep
     #[serde(default = "default_max_backups")]
     pub max_backups: usize,
+    /// Delete snapshots older than this many days, 0 keeps them forever
     #[serde(default = "default_retention_days")]
     pub retention_days: u64,
 }
@@ -37,7 +45,81 @@ impl Default for BackupConfig {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct BootWatchConfig {
+    /// Wait for sys.boot_completed before resetting the boot counter
+    #[serde(default = "default_boot_watch_enabled")]
+    pub enabled: bool,
+    /// Seconds to wait before the boot is treated as failed
+    #[serde(default = "default_boot_watch_timeout")]
+    pub timeout_secs: u64,
+    /// Read properties from this file instead of the live system
+    #[serde(default)]
+    pub prop_file: Option<PathBuf>,
+}
+
+fn default_boot_watch_enabled() -> bool {
+    true
+}
+
+fn default_boot_watch_timeout() -> u64 {
+    300
+}
+
+impl Default for BootWatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_boot_watch_enabled(),
+            timeout_secs: default_boot_watch_timeout(),
+            prop_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct DaemonConfig {
+    /// Keep a resident daemon serving the JSON-RPC socket
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct SyncConfig {
+    /// Extended attributes copied into the workspace, `*` and `?` wildcards allowed
+    #[serde(default = "default_xattr_allow")]
+    pub xattr_allow: Vec<String>,
+    /// Extended attributes never copied, takes precedence over `xattr_allow`
+    #[serde(default)]
+    pub xattr_deny: Vec<String>,
+    /// Compare source and copy metadata after each sync and report mismatches
+    #[serde(default)]
+    pub verify: bool,
+    /// Share storage between identical files of different modules
+    #[serde(default = "default_sync_dedup")]
+    pub dedup: bool,
+}
+
+fn default_xattr_allow() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_sync_dedup() -> bool {
+    true
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            xattr_allow: default_xattr_allow(),
+            xattr_deny: Vec::new(),
+            verify: false,
+            dedup: default_sync_dedup(),
+        }
+    }
+}
+
+/// Backing storage for the module images
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum OverlayMode {
     #[default]
@@ -46,7 +128,8 @@ pub enum OverlayMode {
     Erofs,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+/// Mount strategy for modules without their own rules
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum DefaultMode {
     #[default]
@@ -54,27 +137,60 @@ pub enum DefaultMode {
     Magic,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Config {
+    /// Config schema version
+    #[serde(default = "default_config_version")]
+    pub config_version: u32,
+    /// Directory containing installed modules
     #[serde(default = "default_moduledir")]
     pub moduledir: PathBuf,
+    /// Source name shown for the mounts, detected from the root manager by default
     #[serde(default = "default_mountsource")]
     pub mountsource: String,
+    /// Enable debug logging
+    #[serde(default)]
     pub verbose: bool,
+    /// Extra partitions to mount besides the built-in ones
     #[serde(default, deserialize_with = "deserialize_partitions_flexible")]
+    #[schemars(with = "Vec<String>")]
     pub partitions: Vec<String>,
     #[serde(default)]
     pub overlay_mode: OverlayMode,
+    /// Do not register mounts for umount
     #[serde(default)]
     pub disable_umount: bool,
+    /// Register umount paths even when another umount provider is present
     #[serde(default)]
     pub allow_umount_coexistence: bool,
+    /// Snapshot retention
     #[serde(default, alias = "granary")]
     pub backup: BackupConfig,
+    /// Mount point used for the module storage
     #[serde(default = "default_hybrid_mnt_dir")]
     pub hybrid_mnt_dir: String,
     #[serde(default)]
     pub default_mode: DefaultMode,
+    #[serde(default)]
+    pub boot_watch: BootWatchConfig,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    /// Metadata handling for the module sync
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Read properties for profile matching from this file instead of the live system
+    #[serde(default)]
+    pub profile_prop_file: Option<PathBuf>,
+    /// Module IDs from highest to lowest precedence, unlisted modules follow by priority
+    #[serde(default)]
+    pub module_order: Vec<String>,
+    /// Device-conditional overrides, the first matching profile is applied
+    #[serde(default, rename = "profile", skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<Profile>,
+}
+
+fn default_config_version() -> u32 {
+    migrate::CONFIG_VERSION
 }
 
 fn default_hybrid_mnt_dir() -> String {
@@ -113,6 +229,7 @@ where
 impl Default for Config {
     fn default() -> Self {
         Self {
+            config_version: default_config_version(),
             moduledir: default_moduledir(),
             mountsource: default_mountsource(),
             verbose: false,
@@ -123,35 +240,169 @@ impl Default for Config {
             backup: BackupConfig::default(),
             hybrid_mnt_dir: default_hybrid_mnt_dir(),
             default_mode: DefaultMode::default(),
+            boot_watch: BootWatchConfig::default(),
+            daemon: DaemonConfig::default(),
+            sync: SyncConfig::default(),
+            module_order: Vec::new(),
+            profile_prop_file: None,
+            profiles: Vec::new(),
         }
     }
 }
 
 impl Config {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(path.as_ref()).context("failed to read config file")?;
+    /// 读取配置文件并在内存中迁移到当前版本，不会写回文件（写回见 migrate::upgrade_in_place）
+    pub fn read_table(path: &Path) -> Result<toml::Table> {
+        let content = fs::read_to_string(path).context("failed to read config file")?;
 
-        let config: Config = toml::from_str(&content).context("failed to parse config file")?;
+        let (table, _) = migrate::parse(&content)?;
+
+        Ok(table)
+    }
+
+    pub fn from_table(table: toml::Table) -> Result<Self> {
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .context("failed to parse config file")?;
 
         Ok(config)
     }
 
     pub fn load_default() -> Result<Self> {
-        Self::from_file(CONFIG_FILE_DEFAULT)
+        Ok(Self::load_layered()?.0)
+    }
+
+    /// 读取指定的配置文件，未指定时读取 config.toml 与 config.d；默认配置不存在时使用默认值
+    fn load_from_with_origins(path: Option<&Path>) -> Result<(Self, Origins)> {
+        if let Some(path) = path {
+            let table = Self::read_table(path).with_context(|| {
+                format!("Failed to load config from custom path: {}", path.display())
+            })?;
+            let mut origins = Origins::new();
+            dropin::record_origins(&table, "", &path.to_string_lossy(), &mut origins);
+            return Ok((Self::from_table(table)?, origins));
+        }
+
+        match Self::load_layered() {
+            Ok(layered) => Ok(layered),
+            Err(e) => {
+                let is_not_found = e
+                    .root_cause()
+                    .downcast_ref::<std::io::Error>()
+                    .map(|io_err| io_err.kind() == std::io::ErrorKind::NotFound)
+                    .unwrap_or(false);
+
+                if is_not_found {
+                    Ok((Self::default(), Origins::new()))
+                } else {
+                    Err(e).context(format!(
+                        "Failed to load default config from {}",
+                        CONFIG_FILE_DEFAULT
+                    ))
+                }
+            }
+        }
+    }
+
+    /// 启动流程与所有子命令共用的生效配置：配置文件层叠加第一个命中的 [[profile]]
+    pub fn load_effective(path: Option<&Path>) -> Result<Self> {
+        Ok(Self::load_effective_with_origins(path)?.0)
+    }
+
+    /// 同 load_effective，并返回每个值来自哪个文件或 profile
+    pub fn load_effective_with_origins(path: Option<&Path>) -> Result<(Self, Origins)> {
+        let (mut config, mut origins) = Self::load_from_with_origins(path)?;
+
+        let props = props::source_from(config.profile_prop_file.as_deref());
+        match config.apply_profile(&DeviceFacts::probe(props.as_ref()), &mut origins) {
+            Ok(Some(name)) => utils::log_early(
+                log::Level::Info,
+                format!(">> Config profile selected: {}", name),
+            ),
+            Ok(None) if !config.profiles.is_empty() => {
+                utils::log_early(log::Level::Info, ">> No config profile matched".to_string())
+            }
+            Ok(None) => {}
+            Err(e) => utils::log_early(
+                log::Level::Warn,
+                format!("Failed to apply config profile: {:#}", e),
+            ),
+        }
+
+        Ok((config, origins))
+    }
+
+    /// 加载 config.toml 并按字典序叠加 config.d 片段，同时返回每个值的来源
+    pub fn load_layered() -> Result<(Self, Origins)> {
+        let base = Path::new(CONFIG_FILE_DEFAULT);
+        let fragments = dropin::fragments(Path::new(CONFIG_DROPIN_DIR));
+        let mut origins = Origins::new();
+
+        let mut table = if base.exists() || fragments.is_empty() {
+            let table = Self::read_table(base)?;
+            dropin::record_origins(&table, "", CONFIG_FILE_DEFAULT, &mut origins);
+            table
+        } else {
+            toml::Table::new()
+        };
+
+        dropin::apply(&mut table, &mut origins, &fragments)?;
+
+        Ok((Self::from_table(table)?, origins))
     }
 
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        // 已有文件时只改写变化的值，注释与各层级中无法识别的键都保留在原处
+        if path.exists() {
+            match Self::read_table(path).and_then(Self::from_table) {
+                Ok(existing) => return edit::save_changes(path, &existing, &existing, self),
+                Err(e) => log::warn!(
+                    "Existing config {} is unreadable, overwriting it: {:#}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
         let content = toml::to_string_pretty(self).context("failed to serialize config")?;
 
-        if let Some(parent) = path.as_ref().parent() {
+        if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).context("failed to create config directory")?;
         }
 
-        fs::write(path.as_ref(), content).context("failed to write config file")?;
+        fs::write(path, content).context("failed to write config file")?;
 
         Ok(())
     }
 
+    /// 叠加第一个命中的 [[profile]]，返回其名称；被覆盖的值来源记为 profile:<name>
+    pub fn apply_profile(
+        &mut self,
+        facts: &DeviceFacts,
+        origins: &mut Origins,
+    ) -> Result<Option<String>> {
+        let Some(profile) = profile::select(&self.profiles, facts).cloned() else {
+            return Ok(None);
+        };
+
+        let mut table = toml::Table::try_from(&*self).context("failed to serialize config")?;
+        let mut profile_origins = origins.clone();
+        dropin::merge(
+            &mut table,
+            profile.set,
+            "",
+            &format!("profile:{}", profile.name),
+            &mut profile_origins,
+        );
+        *self = Self::from_table(table)
+            .with_context(|| format!("failed to apply profile '{}'", profile.name))?;
+        *origins = profile_origins;
+
+        Ok(Some(profile.name))
+    }
+
     pub fn merge_with_cli(
         &mut self,
         moduledir: Option<PathBuf>,
@@ -0,0 +1,187 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use toml::{Table, Value};
+
+use super::migrate;
+
+pub const CONFIG_DROPIN_DIR: &str = "/data/adb/meta-hybrid/config.d";
+
+/// 叠加时追加而非覆盖的列表字段（点分路径）
+const APPENDED_LISTS: &[&str] = &["partitions"];
+
+/// 每个生效值（点分路径）来自哪些文件，追加的列表字段可能来自多个文件
+pub type Origins = BTreeMap<String, Vec<String>>;
+
+/// 按文件名字典序列出 config.d 下的 *.toml 片段
+pub fn fragments(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+
+    files.sort();
+    files
+}
+
+/// 将片段依次叠加到基础配置表上：partitions 追加（去重），其余值覆盖
+pub fn apply(base: &mut Table, origins: &mut Origins, files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("failed to read config fragment {}", file.display()))?;
+
+        let (mut fragment, _) = migrate::parse(&content)
+            .with_context(|| format!("failed to parse config fragment {}", file.display()))?;
+
+        // 片段不参与版本声明，迁移时补上的 config_version 不应覆盖基础配置
+        fragment.remove("config_version");
+
+        log::debug!("Applying config fragment {}", file.display());
+        merge(base, fragment, "", &file.to_string_lossy(), origins);
+    }
+
+    Ok(())
+}
+
+pub fn record_origins(table: &Table, prefix: &str, source: &str, origins: &mut Origins) {
+    for (key, value) in table {
+        let path = join(prefix, key);
+        match value {
+            Value::Table(inner) => record_origins(inner, &path, source, origins),
+            _ => {
+                origins.insert(path, vec![source.to_string()]);
+            }
+        }
+    }
+}
+
+pub fn merge(base: &mut Table, overlay: Table, prefix: &str, source: &str, origins: &mut Origins) {
+    for (key, value) in overlay {
+        let path = join(prefix, &key);
+
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(inner)) => {
+                merge(existing, inner, &path, source, origins);
+            }
+            (Some(Value::Array(existing)), Value::Array(items))
+                if APPENDED_LISTS.contains(&path.as_str()) =>
+            {
+                for item in items {
+                    if !existing.contains(&item) {
+                        existing.push(item);
+                    }
+                }
+                origins.entry(path).or_default().push(source.to_string());
+            }
+            (_, Value::Table(inner)) => {
+                origins.retain(|k, _| !k.starts_with(&format!("{}.", path)));
+                record_origins(&inner, &path, source, origins);
+                base.insert(key, Value::Table(inner));
+            }
+            (_, value) => {
+                origins.insert(path, vec![source.to_string()]);
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::config::Config;
+
+    fn layered(base: &str, fragment: &str) -> (Table, Origins) {
+        let mut table: Table = toml::from_str(base).unwrap();
+        let mut origins = Origins::new();
+        record_origins(&table, "", "config.toml", &mut origins);
+        merge(
+            &mut table,
+            toml::from_str(fragment).unwrap(),
+            "",
+            "10-device.toml",
+            &mut origins,
+        );
+        (table, origins)
+    }
+
+    #[test]
+    fn partitions_are_appended_without_duplicates() {
+        let (table, origins) = layered(
+            r#"partitions = ["my_product", "odm"]"#,
+            r#"partitions = ["odm", "my_engineering"]"#,
+        );
+
+        let partitions: Vec<_> = table["partitions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        assert_eq!(partitions, vec!["my_product", "odm", "my_engineering"]);
+        assert_eq!(origins["partitions"], vec!["config.toml", "10-device.toml"]);
+    }
+
+    #[test]
+    fn fragment_narrows_default_xattr_allow() {
+        let mut table = Table::try_from(Config::default()).unwrap();
+        let mut origins = Origins::new();
+        record_origins(&table, "", "default", &mut origins);
+        merge(
+            &mut table,
+            toml::from_str("[sync]\nxattr_allow = [\"security.*\"]\n").unwrap(),
+            "",
+            "10-device.toml",
+            &mut origins,
+        );
+
+        let config = Config::from_table(table).unwrap();
+        assert_eq!(config.sync.xattr_allow, vec!["security.*"]);
+        assert_eq!(origins["sync.xattr_allow"], vec!["10-device.toml"]);
+    }
+
+    #[test]
+    fn module_order_fragments_replace_each_other() {
+        let (table, origins) = layered(r#"module_order = ["a", "b"]"#, r#"module_order = ["c"]"#);
+
+        assert_eq!(
+            table["module_order"].as_array().unwrap(),
+            &vec![Value::from("c")]
+        );
+        assert_eq!(origins["module_order"], vec!["10-device.toml"]);
+    }
+
+    #[test]
+    fn scalars_override_and_tables_merge() {
+        let (table, origins) = layered(
+            "verbose = false\n[sync]\nverify = true\nxattr_deny = []\n",
+            "verbose = true\n[sync]\nverify = false\n",
+        );
+
+        assert_eq!(table["verbose"], Value::Boolean(true));
+        assert_eq!(table["sync"]["verify"], Value::Boolean(false));
+        assert!(table["sync"].get("xattr_deny").is_some());
+        assert_eq!(origins["sync.verify"], vec!["10-device.toml"]);
+        assert_eq!(origins["sync.xattr_deny"], vec!["config.toml"]);
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{collections::BTreeSet, fs, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use toml_edit::{DocumentMut, Item, TableLike};
+
+use super::{
+    config::Config,
+    migrate,
+    validate::{self, ValidationIssue},
+};
+use crate::utils;
+
+/// 读取生效配置中点分路径对应的值
+pub fn get(config: &Config, key: &str) -> Result<toml::Value> {
+    let table = toml::Table::try_from(config).context("failed to serialize config")?;
+    let mut current = &toml::Value::Table(table);
+
+    for segment in key.split('.') {
+        current = current
+            .get(segment)
+            .ok_or_else(|| anyhow!("Config key '{}' is not set", key))?;
+    }
+
+    Ok(current.clone())
+}
+
+/// 修改单个键并原子写回，保留文件中的注释与未知键
+/// 未加引号且无法按类型解析的值会作为字符串重试，例如 overlay_mode = tmpfs
+/// 返回与本次修改无关、修改前就已存在的严重问题，它们不会阻止写入
+pub fn set(path: &Path, key: &str, raw: &str) -> Result<Vec<ValidationIssue>> {
+    let doc = load_document(path)?;
+    let before = parse_document(&doc).ok();
+
+    let mut candidates = Vec::new();
+    if let Ok(value) = raw.parse::<toml_edit::Value>() {
+        candidates.push(value);
+    }
+    candidates.push(toml_edit::Value::from(raw));
+
+    let mut first_error = None;
+    for value in candidates {
+        let mut doc = doc.clone();
+        insert(&mut doc, key, Item::Value(value))?;
+
+        match check(&doc, key, before.as_ref()) {
+            Ok(warnings) => return write_document(path, &doc).map(|_| warnings),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    Err(first_error.unwrap_or_else(|| anyhow!("Invalid value for '{}'", key)))
+}
+
+/// 删除单个键，使其回到默认值
+pub fn unset(path: &Path, key: &str) -> Result<Vec<ValidationIssue>> {
+    let mut doc = load_document(path)?;
+    let before = parse_document(&doc).ok();
+
+    if !remove(&mut doc, key)? {
+        bail!("Config key '{}' is not set", key);
+    }
+
+    let config = parse_document(&doc)
+        .with_context(|| format!("Invalid config after unsetting '{}'", key))?;
+    let warnings = check_semantics(&config, key, before.as_ref())?;
+    write_document(path, &doc)?;
+
+    Ok(warnings)
+}
+
+/// 保存基于 shown（展示给用户的生效配置）编辑得到的 submitted：只把改动过的值写入 path，
+/// 未改动的值即使来自 config.d 或 profile 也不会被复制进文件
+/// base 为 path 单独解析的结果，列表中仅由其他层追加的项不写回文件
+pub fn save_changes(path: &Path, base: &Config, shown: &Config, submitted: &Config) -> Result<()> {
+    let base = toml::Table::try_from(base).context("failed to serialize config")?;
+    let shown = toml::Table::try_from(shown).context("failed to serialize config")?;
+    let submitted = toml::Table::try_from(submitted).context("failed to serialize config")?;
+
+    let mut doc = load_document(path)?;
+
+    let mut changes = Vec::new();
+    changed_values(&shown, &submitted, "", &mut changes);
+
+    for (key, value) in changes {
+        let value = match (value, lookup(&shown, &key), lookup(&base, &key)) {
+            (
+                Some(toml::Value::Array(mut items)),
+                Some(toml::Value::Array(shown_items)),
+                base_items,
+            ) => {
+                let base_items = base_items.and_then(toml::Value::as_array);
+                items.retain(|item| {
+                    !shown_items.contains(item) || base_items.is_some_and(|b| b.contains(item))
+                });
+                Some(toml::Value::Array(items))
+            }
+            (value, _, _) => value,
+        };
+
+        apply_change(&mut doc, &key, value)?;
+    }
+
+    write_document(path, &doc)
+}
+
+/// 把 old -> new 的差异逐键写入文档，其余内容（注释、格式、未知键）保持原样
+pub fn apply_changes(doc: &mut DocumentMut, old: &toml::Table, new: &toml::Table) -> Result<()> {
+    let mut changes = Vec::new();
+    changed_values(old, new, "", &mut changes);
+
+    for (key, value) in changes {
+        apply_change(doc, &key, value)?;
+    }
+
+    Ok(())
+}
+
+/// 收集 old 与 new 之间不同的叶子值（点分路径），None 表示该键被删除；列表整体视为一个值
+fn changed_values(
+    old: &toml::Table,
+    new: &toml::Table,
+    prefix: &str,
+    changes: &mut Vec<(String, Option<toml::Value>)>,
+) {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    for key in keys {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match (old.get(key), new.get(key)) {
+            (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) => {
+                changed_values(old, new, &path, changes);
+            }
+            (old, new) if old != new => changes.push((path, new.cloned())),
+            _ => {}
+        }
+    }
+}
+
+fn lookup<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let mut segments = key.split('.');
+    let mut current = table.get(segments.next()?)?;
+
+    for segment in segments {
+        current = current.get(segment)?;
+    }
+
+    Some(current)
+}
+
+fn apply_change(doc: &mut DocumentMut, key: &str, value: Option<toml::Value>) -> Result<()> {
+    match value {
+        Some(value) => insert(doc, key, to_item(key, value)?),
+        None => remove(doc, key).map(|_| ()),
+    }
+}
+
+/// 借助 toml 序列化得到带格式的条目，表数组仍写成 [[key]] 形式
+fn to_item(key: &str, value: toml::Value) -> Result<Item> {
+    let (_, last) = split_key(key)?;
+
+    let mut wrapper = toml::Table::new();
+    wrapper.insert(last.to_string(), value);
+
+    let mut doc = toml::to_string(&wrapper)
+        .context("failed to serialize config value")?
+        .parse::<DocumentMut>()
+        .context("failed to parse config value")?;
+
+    let mut item = doc
+        .remove(last)
+        .ok_or_else(|| anyhow!("failed to serialize config value for '{}'", key))?;
+
+    // 新插入的表与前文之间空一行
+    if let Item::Table(table) = &mut item {
+        table.decor_mut().set_prefix("\n");
+    }
+
+    Ok(item)
+}
+
+fn load_document(path: &Path) -> Result<DocumentMut> {
+    if !path.exists() {
+        let mut doc = DocumentMut::new();
+        doc.insert(
+            "config_version",
+            toml_edit::value(migrate::CONFIG_VERSION as i64),
+        );
+        return Ok(doc);
+    }
+
+    // 先按需升级旧版本文件，确保编辑的是当前结构
+    migrate::upgrade_in_place(path)?;
+
+    fs::read_to_string(path)
+        .context("failed to read config file")?
+        .parse::<DocumentMut>()
+        .context("failed to parse config file")
+}
+
+fn split_key(key: &str) -> Result<(Vec<&str>, &str)> {
+    let mut segments: Vec<&str> = key.split('.').collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        bail!("Invalid config key '{}'", key);
+    }
+
+    let last = segments.pop().unwrap_or_default();
+    Ok((segments, last))
+}
+
+fn insert(doc: &mut DocumentMut, key: &str, item: Item) -> Result<()> {
+    let (parents, last) = split_key(key)?;
+
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for segment in parents {
+        table = table
+            .entry(segment)
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("Config key '{}' is not a table", segment))?;
+    }
+
+    // 替换已有的值时保留原有的键与装饰，注释不会随之丢失
+    match (table.get_mut(last), item) {
+        (Some(Item::Value(existing)), Item::Value(mut value)) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        (_, item) => {
+            table.insert(last, item);
+        }
+    }
+
+    Ok(())
+}
+
+/// 删除点分路径对应的键，返回该键是否存在
+fn remove(doc: &mut DocumentMut, key: &str) -> Result<bool> {
+    let (parents, last) = split_key(key)?;
+
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for segment in parents {
+        match table.get_mut(segment).and_then(Item::as_table_like_mut) {
+            Some(inner) => table = inner,
+            None => return Ok(false),
+        }
+    }
+
+    Ok(table.remove(last).is_some())
+}
+
+/// 按 Config 校验类型，并确认该键确实被 Config 识别
+fn check(doc: &DocumentMut, key: &str, before: Option<&Config>) -> Result<Vec<ValidationIssue>> {
+    let config = parse_document(doc).with_context(|| format!("Invalid value for '{}'", key))?;
+
+    get(&config, key).map_err(|_| anyhow!("Unknown config key '{}'", key))?;
+
+    check_semantics(&config, key, before)
+}
+
+fn parse_document(doc: &DocumentMut) -> Result<Config> {
+    let table: toml::Table = toml::from_str(&doc.to_string()).context("invalid TOML")?;
+    Config::from_table(table)
+}
+
+/// 只有涉及被修改键、或由本次修改新引入的严重问题才拒绝写入；
+/// 修改前已存在的其他问题（例如 moduledir 缺失、内核不支持 EROFS）作为警告返回
+fn check_semantics(
+    config: &Config,
+    key: &str,
+    before: Option<&Config>,
+) -> Result<Vec<ValidationIssue>> {
+    let existing: Vec<ValidationIssue> = before.map(validate::validate).unwrap_or_default();
+
+    let (blocking, warnings): (Vec<_>, Vec<_>) = validate::validate(config)
+        .into_iter()
+        .filter(ValidationIssue::is_critical)
+        .partition(|issue| {
+            concerns(&issue.field, key)
+                || !existing
+                    .iter()
+                    .any(|e| e.field == issue.field && e.message == issue.message)
+        });
+
+    if !blocking.is_empty() {
+        bail!(
+            "Config failed validation: {}",
+            serde_json::to_string(&blocking)?
+        );
+    }
+
+    Ok(warnings)
+}
+
+/// 校验项字段（如 partitions[0]、boot_watch.prop_file）是否属于点分键 key
+fn concerns(field: &str, key: &str) -> bool {
+    let matches = |outer: &str, inner: &str| {
+        inner == outer
+            || inner
+                .strip_prefix(outer)
+                .is_some_and(|rest| rest.starts_with('.') || rest.starts_with('['))
+    };
+
+    matches(key, field) || matches(field, key)
+}
+
+fn write_document(path: &Path, doc: &DocumentMut) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("failed to create config directory")?;
+    }
+
+    utils::atomic_write(path, doc.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(moduledir: &str) -> Config {
+        Config {
+            moduledir: moduledir.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fields_belong_to_their_dotted_key() {
+        assert!(concerns("moduledir", "moduledir"));
+        assert!(concerns("partitions[0]", "partitions"));
+        assert!(concerns("boot_watch.prop_file", "boot_watch"));
+        assert!(concerns("boot_watch", "boot_watch.enabled"));
+        assert!(!concerns("partitions_extra", "partitions"));
+        assert!(!concerns("moduledir", "verbose"));
+    }
+
+    #[test]
+    fn existing_issue_elsewhere_is_only_a_warning() {
+        let before = config("/nonexistent/hybrid-modules");
+        let mut after = before.clone();
+        after.verbose = !after.verbose;
+
+        let warnings = check_semantics(&after, "verbose", Some(&before)).unwrap();
+
+        assert!(warnings.iter().any(|w| w.field == "moduledir"));
+    }
+
+    #[test]
+    fn issue_on_the_edited_key_blocks_the_write() {
+        let before = config("/nonexistent/hybrid-modules");
+
+        assert!(check_semantics(&before, "moduledir", Some(&before)).is_err());
+    }
+
+    #[test]
+    fn issue_introduced_by_the_edit_blocks_the_write() {
+        let before = config(&std::env::temp_dir().to_string_lossy());
+        // moduledir 改为 / 后与 hybrid_mnt_dir 重叠，问题记在另一个字段上
+        let after = config("/");
+
+        assert!(check_semantics(&after, "moduledir", Some(&before)).is_err());
+        assert!(
+            validate::validate(&after)
+                .iter()
+                .any(|i| i.field == "hybrid_mnt_dir" && i.is_critical())
+        );
+    }
+}
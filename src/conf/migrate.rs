@@ -0,0 +1,377 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use toml::{Table, Value};
+use toml_edit::DocumentMut;
+
+use super::{config::Config, edit};
+use crate::utils;
+
+/// 当前配置结构版本；未声明 config_version 的旧文件视为版本 1
+pub const CONFIG_VERSION: u32 = 2;
+const LEGACY_CONFIG_VERSION: u32 = 1;
+
+/// 已移除的键及原因
+const REMOVED_KEYS: &[(&str, &str)] = &[(
+    "logfile",
+    "Logs are written to the module log and logcat; logfile is no longer used",
+)];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    Unknown,
+    Removed,
+    NewerVersion,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigWarning {
+    pub key: String,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub changes: Vec<String>,
+    pub warnings: Vec<ConfigWarning>,
+    pub backup: Option<PathBuf>,
+}
+
+impl MigrationReport {
+    pub fn upgraded(&self) -> bool {
+        self.from_version < self.to_version
+    }
+
+    fn log(&self) {
+        for change in &self.changes {
+            utils::log_early(log::Level::Info, format!("Config migration: {}", change));
+        }
+
+        for warning in &self.warnings {
+            utils::log_early(
+                log::Level::Warn,
+                format!("Config key '{}': {}", warning.key, warning.message),
+            );
+        }
+    }
+}
+
+type MigrationFn = fn(&mut Table, &mut MigrationReport);
+
+/// 按版本顺序排列的迁移步骤，from 为步骤适用的起始版本
+const MIGRATIONS: &[(u32, MigrationFn)] = &[(1, migrate_v1_to_v2)];
+
+/// v1 -> v2：granary 更名为 backup，partitions 统一为数组，移除废弃键
+fn migrate_v1_to_v2(table: &mut Table, report: &mut MigrationReport) {
+    if let Some(granary) = table.remove("granary") {
+        if table.contains_key("backup") {
+            report.warnings.push(ConfigWarning {
+                key: "granary".to_string(),
+                kind: WarningKind::Removed,
+                message: "Dropped in favour of the existing 'backup' table".to_string(),
+            });
+        } else {
+            table.insert("backup".to_string(), granary);
+            report
+                .changes
+                .push("renamed 'granary' to 'backup'".to_string());
+        }
+    }
+
+    if let Some(Value::String(partitions)) = table.get("partitions") {
+        let list: Vec<Value> = partitions
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| Value::String(p.to_string()))
+            .collect();
+
+        report.changes.push(format!(
+            "converted 'partitions' from string \"{}\" to a list",
+            partitions
+        ));
+        table.insert("partitions".to_string(), Value::Array(list));
+    }
+
+    for (key, reason) in REMOVED_KEYS {
+        if table.remove(*key).is_some() {
+            report.warnings.push(ConfigWarning {
+                key: key.to_string(),
+                kind: WarningKind::Removed,
+                message: reason.to_string(),
+            });
+        }
+    }
+}
+
+/// 将原始配置表升级到当前版本并收集警告
+pub fn migrate(table: &mut Table) -> MigrationReport {
+    let version = table
+        .get("config_version")
+        .and_then(Value::as_integer)
+        .map(|v| v as u32)
+        .unwrap_or(LEGACY_CONFIG_VERSION);
+
+    let mut report = MigrationReport {
+        from_version: version,
+        to_version: version.max(CONFIG_VERSION),
+        ..Default::default()
+    };
+
+    if version > CONFIG_VERSION {
+        report.warnings.push(ConfigWarning {
+            key: "config_version".to_string(),
+            kind: WarningKind::NewerVersion,
+            message: format!(
+                "Config version {} is newer than supported version {}",
+                version, CONFIG_VERSION
+            ),
+        });
+    }
+
+    for (from, step) in MIGRATIONS {
+        if *from >= version {
+            step(table, &mut report);
+            report
+                .changes
+                .push(format!("upgraded config_version {} -> {}", from, from + 1));
+        }
+    }
+
+    if report.upgraded() {
+        table.insert(
+            "config_version".to_string(),
+            Value::Integer(CONFIG_VERSION as i64),
+        );
+    }
+
+    // 经 Config 往返一次后消失的键即为无法识别的键，嵌套表中的子键同样检查
+    if let Ok(config) = Config::from_table(table.clone())
+        && let Ok(known) = Table::try_from(&config)
+    {
+        collect_unknown(table, &known, "", &mut report.warnings);
+    }
+
+    report
+}
+
+fn collect_unknown(table: &Table, known: &Table, prefix: &str, warnings: &mut Vec<ConfigWarning>) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match (value, known.get(key)) {
+            (Value::Table(inner), Some(Value::Table(known))) => {
+                collect_unknown(inner, known, &path, warnings);
+            }
+            // 空列表与默认值一致，序列化时可能被省略
+            (Value::Array(items), None) if items.is_empty() => {}
+            (_, None) => warnings.push(ConfigWarning {
+                key: path,
+                kind: WarningKind::Unknown,
+                message: "Unknown key, it is ignored and kept in the file".to_string(),
+            }),
+            _ => {}
+        }
+    }
+}
+
+/// 配置版本落后时原地升级：先备份原文件，再只改写迁移涉及的键，注释与格式保持不变
+pub fn upgrade_in_place(path: &Path) -> Result<MigrationReport> {
+    let original = fs::read_to_string(path).context("failed to read config file")?;
+    let (table, mut report) = parse(&original)?;
+
+    if !report.upgraded() {
+        return Ok(report);
+    }
+
+    let backup = path.with_extension(format!("toml.v{}.bak", report.from_version));
+
+    let result = utils::atomic_write(&backup, &original)
+        .context("failed to back up config")
+        .and_then(|_| upgraded_document(&original, &table))
+        .and_then(|content| utils::atomic_write(path, content));
+
+    match result {
+        Ok(()) => {
+            report.backup = Some(backup.clone());
+            report.changes.push(format!(
+                "upgraded {} in place, original saved to {}",
+                path.display(),
+                backup.display()
+            ));
+        }
+        Err(e) => utils::log_early(
+            log::Level::Warn,
+            format!("Failed to upgrade {} in place: {:#}", path.display(), e),
+        ),
+    }
+
+    report.log();
+    Ok(report)
+}
+
+fn upgraded_document(original: &str, migrated: &Table) -> Result<String> {
+    let before: Table = toml::from_str(original).context("failed to parse config file")?;
+    let mut doc = original
+        .parse::<DocumentMut>()
+        .context("failed to parse config file")?;
+
+    edit::apply_changes(&mut doc, &before, migrated)?;
+
+    Ok(doc.to_string())
+}
+
+pub fn parse(content: &str) -> Result<(Table, MigrationReport)> {
+    let mut table: Table = toml::from_str(content).context("failed to parse config file")?;
+    let report = migrate(&mut table);
+    Ok((table, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    const V1: &str = r#"# hybrid mount settings
+moduledir = "/data/adb/modules/"
+# partitions to overlay
+partitions = "odm, my_product,"
+logfile = "/data/local/tmp/hybrid.log"
+
+[granary]
+# keep a week of snapshots
+max_backups = 7
+future_knob = true
+"#;
+
+    fn keys(report: &MigrationReport, kind: fn(&WarningKind) -> bool) -> Vec<&str> {
+        report
+            .warnings
+            .iter()
+            .filter(|w| kind(&w.kind))
+            .map(|w| w.key.as_str())
+            .collect()
+    }
+
+    fn temp_config(content: &str) -> (TempDir, PathBuf) {
+        let dir = TempDir::new("migrate");
+        let path = dir.path().join("config.toml");
+        fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn v1_is_upgraded_in_memory() {
+        let (table, report) = parse(V1).unwrap();
+
+        assert_eq!(
+            (report.from_version, report.to_version),
+            (1, CONFIG_VERSION)
+        );
+        assert_eq!(table["config_version"].as_integer(), Some(2));
+        assert_eq!(
+            table["partitions"],
+            Value::Array(vec!["odm".into(), "my_product".into()])
+        );
+        assert!(!table.contains_key("granary"));
+        assert!(!table.contains_key("logfile"));
+        assert_eq!(table["backup"]["max_backups"].as_integer(), Some(7));
+        assert_eq!(
+            keys(&report, |k| matches!(k, WarningKind::Removed)),
+            vec!["logfile"]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_at_any_depth() {
+        let (table, report) = parse(V1).unwrap();
+
+        assert_eq!(
+            keys(&report, |k| matches!(k, WarningKind::Unknown)),
+            vec!["backup.future_knob"]
+        );
+        assert_eq!(table["backup"]["future_knob"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn current_version_is_left_alone() {
+        let (table, report) = parse("config_version = 2\nverbose = true\nprofile = []\n").unwrap();
+
+        assert!(!report.upgraded());
+        assert!(report.changes.is_empty());
+        assert!(report.warnings.is_empty());
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn newer_version_is_flagged() {
+        let (_, report) = parse("config_version = 9\n").unwrap();
+
+        assert_eq!(report.to_version, 9);
+        assert_eq!(
+            keys(&report, |k| matches!(k, WarningKind::NewerVersion)),
+            vec!["config_version"]
+        );
+    }
+
+    #[test]
+    fn granary_is_dropped_when_backup_exists() {
+        let (table, report) =
+            parse("[granary]\nmax_backups = 1\n[backup]\nmax_backups = 5\n").unwrap();
+
+        assert_eq!(table["backup"]["max_backups"].as_integer(), Some(5));
+        assert_eq!(
+            keys(&report, |k| matches!(k, WarningKind::Removed)),
+            vec!["granary"]
+        );
+    }
+
+    #[test]
+    fn upgrade_in_place_keeps_comments_and_backs_up() {
+        let (_dir, path) = temp_config(V1);
+
+        let report = upgrade_in_place(&path).unwrap();
+        let upgraded = fs::read_to_string(&path).unwrap();
+
+        let backup = report.backup.unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V1);
+        assert!(upgraded.starts_with("# hybrid mount settings\n"));
+        assert!(
+            upgraded.contains("# partitions to overlay\npartitions = [\"odm\", \"my_product\"]\n")
+        );
+        assert!(upgraded.contains("config_version = 2\n"));
+        assert!(upgraded.contains("future_knob = true"));
+        assert!(!upgraded.contains("logfile"));
+        assert!(!upgraded.contains("[granary]"));
+
+        // 已是当前版本时不再改写
+        let again = upgrade_in_place(&path).unwrap();
+        assert!(again.backup.is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), upgraded);
+    }
+
+    #[test]
+    fn reading_does_not_rewrite_the_file() {
+        let (_dir, path) = temp_config(V1);
+
+        let table = Config::read_table(&path).unwrap();
+
+        assert_eq!(table["config_version"].as_integer(), Some(2));
+        assert_eq!(fs::read_to_string(&path).unwrap(), V1);
+    }
+}
@@ -4,3 +4,9 @@
 pub mod cli;
 pub mod cli_handlers;
 pub mod config;
+pub mod dropin;
+pub mod edit;
+pub mod migrate;
+pub mod profile;
+pub mod schema;
+pub mod validate;
@@ -0,0 +1,232 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{cmp::Ordering, collections::BTreeMap, fs};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::props::PropertySource;
+
+/// 设备条件配置块，对应 config.toml 中的 [[profile]]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default, rename = "match")]
+    pub conditions: ProfileMatch,
+    /// 命中后叠加到配置上的设置，规则与 config.d 相同：列表追加，其余覆盖
+    #[serde(default)]
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+    pub set: toml::Table,
+}
+
+/// 所有已设置的条件都满足时才算命中
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct ProfileMatch {
+    #[serde(default)]
+    pub kernel_min: Option<String>,
+    #[serde(default)]
+    pub kernel_max: Option<String>,
+    #[serde(default)]
+    pub sdk_min: Option<u32>,
+    #[serde(default)]
+    pub sdk_max: Option<u32>,
+    /// 属性名到期望值，值以 * 结尾时按前缀匹配
+    #[serde(default)]
+    pub props: BTreeMap<String, String>,
+}
+
+/// 匹配所需的设备信息
+pub struct DeviceFacts<'a> {
+    pub kernel: Option<String>,
+    pub props: &'a dyn PropertySource,
+}
+
+impl<'a> DeviceFacts<'a> {
+    pub fn probe(props: &'a dyn PropertySource) -> Self {
+        Self {
+            kernel: fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|s| s.trim().to_string()),
+            props,
+        }
+    }
+}
+
+impl ProfileMatch {
+    pub fn matches(&self, facts: &DeviceFacts) -> bool {
+        if self.kernel_min.is_some() || self.kernel_max.is_some() {
+            let Some(kernel) = &facts.kernel else {
+                return false;
+            };
+
+            if self
+                .kernel_min
+                .as_ref()
+                .is_some_and(|min| compare_versions(kernel, min) == Ordering::Less)
+            {
+                return false;
+            }
+
+            if self
+                .kernel_max
+                .as_ref()
+                .is_some_and(|max| compare_versions(kernel, max) == Ordering::Greater)
+            {
+                return false;
+            }
+        }
+
+        if self.sdk_min.is_some() || self.sdk_max.is_some() {
+            let Some(sdk) = facts
+                .props
+                .get("ro.build.version.sdk")
+                .and_then(|s| s.parse::<u32>().ok())
+            else {
+                return false;
+            };
+
+            if self.sdk_min.is_some_and(|min| sdk < min)
+                || self.sdk_max.is_some_and(|max| sdk > max)
+            {
+                return false;
+            }
+        }
+
+        self.props.iter().all(|(key, expected)| {
+            facts
+                .props
+                .get(key)
+                .is_some_and(|actual| match expected.strip_suffix('*') {
+                    Some(prefix) => actual.starts_with(prefix),
+                    None => actual == *expected,
+                })
+        })
+    }
+}
+
+/// 按边界给出的数字段比较版本号，例如 "5.10.198-android12" 与 "5.10" 视为相等
+fn compare_versions(actual: &str, bound: &str) -> Ordering {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split(|c: char| !c.is_ascii_digit() && c != '.')
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+
+    let (a, b) = (parse(actual), parse(bound));
+    for i in 0..b.len() {
+        match a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)) {
+            Ordering::Equal => continue,
+            other => return other,
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// 返回第一个命中的配置块
+pub fn select<'p>(profiles: &'p [Profile], facts: &DeviceFacts) -> Option<&'p Profile> {
+    profiles.iter().find(|p| p.conditions.matches(facts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Props(BTreeMap<&'static str, &'static str>);
+
+    impl PropertySource for Props {
+        fn get(&self, key: &str) -> Option<String> {
+            self.0.get(key).map(|v| v.to_string())
+        }
+    }
+
+    fn oppo_a15() -> Props {
+        Props(BTreeMap::from([
+            ("ro.build.version.sdk", "34"),
+            ("ro.product.brand", "OPPO"),
+            ("ro.product.model", "CPH2185"),
+        ]))
+    }
+
+    fn facts<'a>(kernel: Option<&str>, props: &'a Props) -> DeviceFacts<'a> {
+        DeviceFacts {
+            kernel: kernel.map(str::to_string),
+            props,
+        }
+    }
+
+    #[test]
+    fn versions_compare_up_to_the_bound() {
+        assert_eq!(
+            compare_versions("5.10.198-android12-9", "5.10"),
+            Ordering::Equal
+        );
+        assert_eq!(compare_versions("5.10.198", "5.10.200"), Ordering::Less);
+        assert_eq!(compare_versions("5.15.94", "5.10"), Ordering::Greater);
+        assert_eq!(compare_versions("6.1", "6.1.0"), Ordering::Equal);
+        assert_eq!(compare_versions("4.19.157-perf+", "5.4"), Ordering::Less);
+    }
+
+    #[test]
+    fn kernel_range_is_inclusive() {
+        let props = oppo_a15();
+        let range = ProfileMatch {
+            kernel_min: Some("5.4".into()),
+            kernel_max: Some("5.10".into()),
+            ..Default::default()
+        };
+
+        assert!(range.matches(&facts(Some("5.4.254-qgki"), &props)));
+        assert!(range.matches(&facts(Some("5.10.198-android12-9"), &props)));
+        assert!(!range.matches(&facts(Some("5.15.94"), &props)));
+        assert!(!range.matches(&facts(None, &props)));
+    }
+
+    #[test]
+    fn sdk_and_props_must_all_match() {
+        let props = oppo_a15();
+        let matching = ProfileMatch {
+            sdk_min: Some(33),
+            props: BTreeMap::from([("ro.product.brand".into(), "OP*".into())]),
+            ..Default::default()
+        };
+        let wrong_model = ProfileMatch {
+            props: BTreeMap::from([("ro.product.model".into(), "CPH2".into())]),
+            ..Default::default()
+        };
+        let too_new = ProfileMatch {
+            sdk_min: Some(35),
+            ..Default::default()
+        };
+
+        assert!(matching.matches(&facts(None, &props)));
+        assert!(!wrong_model.matches(&facts(None, &props)));
+        assert!(!too_new.matches(&facts(None, &props)));
+        assert!(ProfileMatch::default().matches(&facts(None, &props)));
+    }
+
+    #[test]
+    fn first_matching_profile_is_selected() {
+        let props = oppo_a15();
+        let profile = |name: &str, brand: &str| Profile {
+            name: name.into(),
+            conditions: ProfileMatch {
+                props: BTreeMap::from([("ro.product.brand".into(), brand.into())]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let profiles = [
+            profile("xiaomi", "Xiaomi"),
+            profile("oplus", "OPPO"),
+            profile("any", "*"),
+        ];
+
+        let selected = select(&profiles, &facts(None, &props)).map(|p| p.name.as_str());
+        assert_eq!(selected, Some("oplus"));
+    }
+}
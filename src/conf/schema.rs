@@ -0,0 +1,45 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use schemars::{Schema, schema_for};
+
+use super::{cli_handlers::DiagnosticIssueJson, config::Config};
+use crate::core::{
+    granary::Snapshot, inventory::ModuleRules, planner::ConflictEntry, state::RuntimeState,
+};
+
+/// 可导出 JSON Schema 的数据结构
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SchemaTarget {
+    Config,
+    ModuleRules,
+    RuntimeState,
+    Snapshot,
+    Conflicts,
+    Diagnostics,
+}
+
+pub fn generate(target: SchemaTarget) -> Schema {
+    match target {
+        SchemaTarget::Config => schema_for!(Config),
+        SchemaTarget::ModuleRules => schema_for!(ModuleRules),
+        SchemaTarget::RuntimeState => schema_for!(RuntimeState),
+        SchemaTarget::Snapshot => schema_for!(Snapshot),
+        SchemaTarget::Conflicts => schema_for!(Vec<ConflictEntry>),
+        SchemaTarget::Diagnostics => schema_for!(Vec<DiagnosticIssueJson>),
+    }
+}
+
+/// 以命令行名称为键导出全部 Schema
+pub fn generate_all() -> BTreeMap<String, Schema> {
+    SchemaTarget::value_variants()
+        .iter()
+        .filter_map(|target| {
+            let name = target.to_possible_value()?.get_name().to_string();
+            Some((name, generate(*target)))
+        })
+        .collect()
+}
@@ -0,0 +1,185 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use super::{
+    config::{Config, OverlayMode},
+    migrate,
+};
+use crate::{core::planner::DiagnosticLevel, utils};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub field: String,
+    pub severity: DiagnosticLevel,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl ValidationIssue {
+    fn new(field: &str, severity: DiagnosticLevel, message: String, suggestion: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            severity,
+            message,
+            suggestion: (!suggestion.is_empty()).then(|| suggestion.to_string()),
+        }
+    }
+
+    pub fn is_critical(&self) -> bool {
+        matches!(self.severity, DiagnosticLevel::Critical)
+    }
+}
+
+/// 对照当前系统检查配置的语义约束
+pub fn validate(config: &Config) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    check_version(config, &mut issues);
+    check_moduledir(config, &mut issues);
+    check_mnt_dir(config, &mut issues);
+    check_partitions(config, &mut issues);
+    check_overlay_mode(config, &mut issues);
+    check_boot_watch(config, &mut issues);
+
+    if config.mountsource.trim().is_empty() {
+        issues.push(ValidationIssue::new(
+            "mountsource",
+            DiagnosticLevel::Warning,
+            "Mount source is empty".to_string(),
+            "Set it to KSU or APatch, or remove the key to auto-detect",
+        ));
+    }
+
+    issues
+}
+
+fn check_version(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    if config.config_version > migrate::CONFIG_VERSION {
+        issues.push(ValidationIssue::new(
+            "config_version",
+            DiagnosticLevel::Warning,
+            format!(
+                "Config version {} is newer than supported version {}",
+                config.config_version,
+                migrate::CONFIG_VERSION
+            ),
+            "Update meta-hybrid or regenerate the config with gen-config",
+        ));
+    }
+}
+
+fn check_moduledir(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    if !config.moduledir.is_absolute() {
+        issues.push(ValidationIssue::new(
+            "moduledir",
+            DiagnosticLevel::Critical,
+            format!("{} is not an absolute path", config.moduledir.display()),
+            "Use an absolute path such as /data/adb/modules/",
+        ));
+    } else if !config.moduledir.is_dir() {
+        issues.push(ValidationIssue::new(
+            "moduledir",
+            DiagnosticLevel::Critical,
+            format!("{} does not exist", config.moduledir.display()),
+            "Point moduledir at an existing module directory such as /data/adb/modules/",
+        ));
+    }
+}
+
+fn check_mnt_dir(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    let mnt_dir = PathBuf::from(&config.hybrid_mnt_dir);
+
+    if !mnt_dir.is_absolute() {
+        issues.push(ValidationIssue::new(
+            "hybrid_mnt_dir",
+            DiagnosticLevel::Critical,
+            format!("{} is not an absolute path", config.hybrid_mnt_dir),
+            "Use an absolute path such as /mnt/vendor/meta-hybrid",
+        ));
+        return;
+    }
+
+    if mnt_dir.starts_with(&config.moduledir) || config.moduledir.starts_with(&mnt_dir) {
+        issues.push(ValidationIssue::new(
+            "hybrid_mnt_dir",
+            DiagnosticLevel::Critical,
+            format!(
+                "{} overlaps moduledir {}",
+                config.hybrid_mnt_dir,
+                config.moduledir.display()
+            ),
+            "Move hybrid_mnt_dir outside of moduledir, e.g. /mnt/vendor/meta-hybrid",
+        ));
+    }
+}
+
+fn check_partitions(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    for (i, partition) in config.partitions.iter().enumerate() {
+        let field = format!("partitions[{}]", i);
+
+        if partition.is_empty() || partition.contains('/') {
+            issues.push(ValidationIssue::new(
+                &field,
+                DiagnosticLevel::Critical,
+                format!("'{}' is not a valid partition name", partition),
+                "Use a bare partition name such as vendor or my_product",
+            ));
+            continue;
+        }
+
+        let present = Path::new("/").join(partition).is_dir()
+            || Path::new("/system").join(partition).is_dir();
+
+        if !present {
+            issues.push(ValidationIssue::new(
+                &field,
+                DiagnosticLevel::Warning,
+                format!("Partition '{}' is not present on this device", partition),
+                "Remove it from partitions",
+            ));
+        }
+    }
+}
+
+fn check_overlay_mode(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    if config.overlay_mode == OverlayMode::Erofs && !utils::is_erofs_supported() {
+        issues.push(ValidationIssue::new(
+            "overlay_mode",
+            DiagnosticLevel::Critical,
+            "Kernel does not support EROFS".to_string(),
+            "Use overlay_mode = \"tmpfs\" or \"ext4\"",
+        ));
+    }
+}
+
+fn check_boot_watch(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    let watch = &config.boot_watch;
+
+    if !watch.enabled {
+        return;
+    }
+
+    if watch.timeout_secs == 0 {
+        issues.push(ValidationIssue::new(
+            "boot_watch.timeout_secs",
+            DiagnosticLevel::Warning,
+            "A zero timeout never waits for sys.boot_completed".to_string(),
+            "Use a timeout of a few minutes, e.g. 300",
+        ));
+    }
+
+    if let Some(prop_file) = &watch.prop_file
+        && !prop_file.exists()
+    {
+        issues.push(ValidationIssue::new(
+            "boot_watch.prop_file",
+            DiagnosticLevel::Warning,
+            format!("{} does not exist", prop_file.display()),
+            "Remove prop_file to read live system properties",
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use crate::{
+    core::{inventory::Module, manifest},
+    defs, utils,
+};
+
+const CAPABILITY_XATTR: &str = "security.capability";
+
+/// 跨模块去重的结果
+#[derive(Debug, Default)]
+pub struct DedupResult {
+    /// 被链接到已有副本的文件数
+    pub files: usize,
+    /// 节省的字节数
+    pub bytes: u64,
+}
+
+/// 共享 inode 时必须一致的元数据，只有修改时间不同的副本改用 reflink
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Shape {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: i64,
+    mtime_nsec: i64,
+    xattrs: BTreeMap<String, String>,
+}
+
+struct Candidate {
+    path: PathBuf,
+    inode: (u64, u64),
+    shape: Shape,
+}
+
+/// 按内容合并工作区中相同的文件：元数据一致时建立硬链接，
+/// 否则在支持 reflink 的文件系统上共享数据块
+pub fn deduplicate(modules: &[Module], target_base: &Path) -> DedupResult {
+    let mut by_size: HashMap<u64, Vec<Candidate>> = HashMap::new();
+
+    for module in modules {
+        let root = target_base.join(&module.id);
+        for entry in WalkDir::new(&root).min_depth(1).into_iter().flatten() {
+            if !entry.file_type().is_file() || entry.file_name() == defs::SYNC_MANIFEST_FILE_NAME {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.len() == 0 {
+                continue;
+            }
+
+            by_size.entry(metadata.len()).or_default().push(Candidate {
+                path: entry.path().to_path_buf(),
+                inode: (metadata.dev(), metadata.ino()),
+                shape: Shape {
+                    mode: metadata.mode(),
+                    uid: metadata.uid(),
+                    gid: metadata.gid(),
+                    mtime: metadata.mtime(),
+                    mtime_nsec: metadata.mtime_nsec(),
+                    xattrs: manifest::read_xattrs(entry.path()),
+                },
+            });
+        }
+    }
+
+    // 大小相同且不止一个 inode 时才需要计算哈希
+    let groups: Vec<(u64, Vec<Candidate>)> = by_size
+        .into_iter()
+        .filter(|(_, candidates)| candidates.iter().any(|c| c.inode != candidates[0].inode))
+        .collect();
+
+    let hashed: Vec<(u64, Vec<(String, Candidate)>)> = groups
+        .into_par_iter()
+        .map(|(size, candidates)| {
+            let mut cache: HashMap<(u64, u64), String> = HashMap::new();
+            let hashed = candidates
+                .into_iter()
+                .filter_map(|c| {
+                    let hash = match cache.get(&c.inode) {
+                        Some(hash) => hash.clone(),
+                        None => {
+                            let hash = manifest::hash_file(&c.path).ok()?;
+                            cache.insert(c.inode, hash.clone());
+                            hash
+                        }
+                    };
+                    Some((hash, c))
+                })
+                .collect();
+            (size, hashed)
+        })
+        .collect();
+
+    let mut result = DedupResult::default();
+    let mut reflink_supported = true;
+
+    for (size, candidates) in hashed {
+        let mut by_hash: BTreeMap<String, Vec<Candidate>> = BTreeMap::new();
+        for (hash, candidate) in candidates {
+            by_hash.entry(hash).or_default().push(candidate);
+        }
+
+        for (_, mut same_content) in by_hash {
+            // 按路径排序，保证每次选出的保留副本一致
+            same_content.sort_by(|a, b| a.path.cmp(&b.path));
+            let origin = &same_content[0];
+            let mut canonical: HashMap<&Shape, &Candidate> = HashMap::new();
+
+            for candidate in &same_content {
+                let Some(keep) = canonical.get(&candidate.shape) else {
+                    canonical.insert(&candidate.shape, candidate);
+                    if candidate.inode != origin.inode
+                        && reflink_supported
+                        && reflink_allowed(&candidate.shape)
+                    {
+                        match reflink(&origin.path, &candidate.path) {
+                            Ok(()) => {
+                                result.files += 1;
+                                result.bytes += size;
+                            }
+                            Err(e) => {
+                                log::debug!("Reflink unavailable, keeping copies: {:#}", e);
+                                reflink_supported = false;
+                            }
+                        }
+                    }
+                    continue;
+                };
+
+                if candidate.inode == keep.inode {
+                    continue;
+                }
+
+                match replace_with_link(&keep.path, &candidate.path) {
+                    Ok(()) => {
+                        result.files += 1;
+                        result.bytes += size;
+                    }
+                    Err(e) => log::warn!(
+                        "Failed to deduplicate {}: {:#}",
+                        candidate.path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+    }
+
+    if result.files > 0 {
+        log::info!(
+            ">> Deduplicated {} files across modules, {} bytes saved",
+            result.files,
+            result.bytes
+        );
+    }
+
+    result
+}
+
+/// 写入会清除 setuid/setgid 与文件能力，这类文件不做 reflink
+fn reflink_allowed(shape: &Shape) -> bool {
+    shape.mode & 0o6000 == 0 && !shape.xattrs.contains_key(CAPABILITY_XATTR)
+}
+
+fn reflink(origin: &Path, target: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(target)?;
+    utils::reflink_into(origin, target)?;
+    utils::set_timestamps(target, &metadata)
+}
+
+/// 先在旁边建立链接再原子替换，中途失败不会丢失原文件
+/// 替换会改变父目录的修改时间，完成后还原
+fn replace_with_link(keep: &Path, target: &Path) -> Result<()> {
+    let name = target
+        .file_name()
+        .context("Invalid dedup target")?
+        .to_string_lossy();
+    let temp = target.with_file_name(format!(".dedup_{}", name));
+    let parent = target.parent().context("Invalid dedup target")?;
+    let parent_metadata = fs::symlink_metadata(parent)?;
+
+    let _ = fs::remove_file(&temp);
+    fs::hard_link(keep, &temp)?;
+    fs::rename(&temp, target).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })?;
+
+    utils::set_timestamps(parent, &parent_metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::{core::inventory::ModuleRules, utils::test_utils::TempDir};
+
+    fn module(id: &str) -> Module {
+        Module {
+            id: id.to_string(),
+            source_path: PathBuf::from("/data/adb/modules").join(id),
+            rules: ModuleRules::default(),
+        }
+    }
+
+    fn set_mtime(path: &Path, secs: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    /// 在 a、b 两个模块的工作区副本中写入相同内容的文件
+    fn workspace(root: &Path) -> (PathBuf, PathBuf) {
+        let paths: Vec<_> = ["a", "b"]
+            .iter()
+            .map(|id| {
+                let dir = root.join(id).join("system/bin");
+                fs::create_dir_all(&dir).unwrap();
+                let path = dir.join("tool");
+                fs::write(&path, "#!/bin/sh\necho hybrid\n").unwrap();
+                set_mtime(&path, 1_700_000_000);
+                path
+            })
+            .collect();
+        (paths[0].clone(), paths[1].clone())
+    }
+
+    fn shape(mode: u32, xattrs: &[&str]) -> Shape {
+        Shape {
+            mode,
+            uid: 0,
+            gid: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            xattrs: xattrs
+                .iter()
+                .map(|name| (name.to_string(), String::new()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn identical_shape_is_hardlinked() {
+        let dir = TempDir::new("dedup-same");
+        let (a, b) = workspace(dir.path());
+
+        let result = deduplicate(&[module("a"), module("b")], dir.path());
+
+        assert_eq!(result.files, 1);
+        assert_eq!(
+            fs::metadata(&a).unwrap().ino(),
+            fs::metadata(&b).unwrap().ino()
+        );
+    }
+
+    #[test]
+    fn different_shape_is_never_hardlinked() {
+        let changes: [fn(&Path); 2] = [
+            |path| set_mtime(path, 1_700_000_001),
+            |path| {
+                let mut permissions = fs::metadata(path).unwrap().permissions();
+                std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+                fs::set_permissions(path, permissions).unwrap();
+            },
+        ];
+
+        for change in changes {
+            let dir = TempDir::new("dedup-differ");
+            let (a, b) = workspace(dir.path());
+            change(&b);
+            let before = fs::metadata(&b).unwrap();
+
+            deduplicate(&[module("a"), module("b")], dir.path());
+
+            // 支持 reflink 时可能共享数据块，但 inode 与元数据各自保留
+            let after = fs::metadata(&b).unwrap();
+            assert_ne!(fs::metadata(&a).unwrap().ino(), after.ino());
+            assert_eq!(
+                (after.mode(), after.mtime(), after.mtime_nsec()),
+                (before.mode(), before.mtime(), before.mtime_nsec())
+            );
+        }
+    }
+
+    #[test]
+    fn setuid_and_capability_files_are_never_reflinked() {
+        assert!(reflink_allowed(&shape(0o100755, &["security.selinux"])));
+        assert!(!reflink_allowed(&shape(0o104755, &[])));
+        assert!(!reflink_allowed(&shape(0o102755, &[])));
+        assert!(!reflink_allowed(&shape(0o100755, &[CAPABILITY_XATTR])));
+    }
+
+    #[test]
+    fn replace_with_link_restores_parent_timestamps() {
+        let dir = TempDir::new("dedup-parent");
+        let (a, b) = workspace(dir.path());
+        let parent = b.parent().unwrap();
+        File::open(parent)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+            .unwrap();
+        let before = fs::metadata(parent).unwrap();
+
+        replace_with_link(&a, &b).unwrap();
+
+        let after = fs::metadata(parent).unwrap();
+        assert_eq!(
+            fs::metadata(&a).unwrap().ino(),
+            fs::metadata(&b).unwrap().ino()
+        );
+        assert_eq!(
+            (after.mtime(), after.mtime_nsec()),
+            (before.mtime(), before.mtime_nsec())
+        );
+    }
+}
@@ -7,12 +7,14 @@ use std::{
 };
 
 use anyhow::{Context, Result};
+use procfs::process::Process;
+use rustix::mount::{UnmountFlags, unmount};
 
 use crate::{
     conf::config,
-    core::planner::{MountPlan, OverlayOperation},
+    core::planner::{DiagnosticIssue, DiagnosticLevel, MagicWork, MountPlan, OverlayOperation},
     defs,
-    mount::{magic_mount, overlayfs},
+    mount::overlayfs,
     utils,
 };
 
@@ -20,151 +22,389 @@ use crate::{
 pub struct ExecutionResult {
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    /// 因后续阶段失败而被回滚的挂载点（按卸载顺序）
+    pub rolled_back_mounts: Vec<String>,
+    /// 导致回滚的错误信息
+    pub failure: Option<String>,
+    /// 执行阶段产生的诊断信息
+    pub diagnostics: Vec<DiagnosticIssue>,
 }
 
-/// 挂载事务守卫：负责记录并在必要时回滚挂载操作
+/// 本事务创建的一个挂载
+struct TrackedMount {
+    mnt_id: i32,
+    target: String,
+}
+
+/// 事务内的记录位置
+#[derive(Clone, Copy, Default)]
+struct Checkpoint {
+    mounts: usize,
+    umounts: usize,
+}
+
+/// 挂载事务守卫：按操作记录本次启动创建的挂载，并在必要时只回滚这些挂载
 struct MountTransaction {
-    mounted_targets: Vec<String>,
+    mounts: Vec<TrackedMount>,
+    /// 登记的 try_umount 目标，全部阶段成功后才提交给 KSU
+    umounts: Vec<String>,
 }
 
 impl MountTransaction {
     fn new() -> Self {
-        Self { mounted_targets: Vec::new() }
+        Self {
+            mounts: Vec::new(),
+            umounts: Vec::new(),
+        }
     }
 
-    /// 记录一个新的成功挂载
-    fn register(&mut self, target: &str) {
-        self.mounted_targets.push(target.to_string());
+    /// 当前记录位置，配合 rollback_to 撤销之后的挂载
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            mounts: self.mounts.len(),
+            umounts: self.umounts.len(),
+        }
     }
 
-    /// 执行回滚：按挂载相反的顺序强制卸载
-    fn rollback(self) {
-        if self.mounted_targets.is_empty() { return; }
-        log::warn!("Rolling back {} mount points due to inconsistency...", self.mounted_targets.len());
-        
-        for target in self.mounted_targets.into_iter().rev() {
-            // 这里调用底层的卸载逻辑，建议在 utils 中实现一个通用的强制卸载
-            let _ = crate::try_umount::send_umountable(&target);
+    /// 执行一次挂载操作，登记其新增且位于 roots 之下的挂载（即使操作失败也会登记已留下的挂载）
+    fn track<T>(&mut self, roots: &[PathBuf], op: impl FnOnce() -> Result<T>) -> Result<T> {
+        let before = snapshot_mount_ids();
+        let result = op();
+        self.mounts.extend(mounts_since(&before, roots));
+        self.umounts.extend(crate::try_umount::take_pending());
+        result
+    }
+
+    /// 按挂载相反的顺序卸载 mark 之后登记的挂载，返回已回滚的挂载点
+    /// 这些挂载登记的 try_umount 目标一并丢弃
+    fn rollback_to(&mut self, mark: Checkpoint) -> Vec<String> {
+        self.umounts.truncate(mark.umounts);
+        let mounts = self.mounts.split_off(mark.mounts);
+        if mounts.is_empty() {
+            return Vec::new();
         }
+        log::warn!(
+            "Rolling back {} mount points due to inconsistency...",
+            mounts.len()
+        );
+
+        let mut rolled_back = Vec::new();
+        for mount in mounts.into_iter().rev() {
+            match top_mount_id(&mount.target) {
+                None => {
+                    log::debug!("Skip rollback of {}: already detached", mount.target);
+                    continue;
+                }
+                Some(id) if id != mount.mnt_id => {
+                    if snapshot_mount_ids().contains(&mount.mnt_id) {
+                        log::error!(
+                            "Cannot roll back mount {} (id {}): covered by mount {} not created by us",
+                            mount.target,
+                            mount.mnt_id,
+                            id
+                        );
+                    } else {
+                        log::debug!("Skip rollback of {}: already detached", mount.target);
+                    }
+                    continue;
+                }
+                Some(_) => {}
+            }
+
+            match force_unmount(&mount.target) {
+                Ok(()) => {
+                    log::info!("Rolled back mount: {}", mount.target);
+                    rolled_back.push(mount.target);
+                }
+                Err(e) => log::error!("Failed to roll back mount {}: {:#}", mount.target, e),
+            }
+        }
+        rolled_back
+    }
+
+    fn rollback(mut self) -> Vec<String> {
+        self.rollback_to(Checkpoint::default())
+    }
+
+    /// 所有阶段成功后提交登记的 try_umount 目标
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn commit_umounts(&mut self) -> Result<()> {
+        crate::try_umount::commit(std::mem::take(&mut self.umounts))
+    }
+}
+
+/// 先尝试正常卸载，失败后使用 DETACH 强制分离
+fn force_unmount(target: &str) -> Result<()> {
+    if overlayfs::utils::umount_dir(target).is_ok() {
+        return Ok(());
     }
+    unmount(target, UnmountFlags::DETACH)
+        .with_context(|| format!("Failed to detach {}", target))?;
+    Ok(())
 }
 
+/// 当前挂载命名空间内所有挂载的 mnt_id
+fn snapshot_mount_ids() -> HashSet<i32> {
+    Process::myself()
+        .and_then(|p| p.mountinfo())
+        .map(|m| m.0.iter().map(|e| e.mnt_id).collect())
+        .unwrap_or_default()
+}
+
+/// 挂载点上最上层挂载的 mnt_id（mountinfo 中靠后的条目覆盖靠前的）
+fn top_mount_id(target: &str) -> Option<i32> {
+    Process::myself()
+        .and_then(|p| p.mountinfo())
+        .ok()?
+        .0
+        .iter()
+        .rev()
+        .find(|e| e.mount_point == Path::new(target))
+        .map(|e| e.mnt_id)
+}
+
+/// 找出快照之后新增、且位于 roots 之下的挂载，按挂载顺序返回
+fn mounts_since(before: &HashSet<i32>, roots: &[PathBuf]) -> Vec<TrackedMount> {
+    Process::myself()
+        .and_then(|p| p.mountinfo())
+        .map(|m| {
+            m.0.iter()
+                .filter(|e| !before.contains(&e.mnt_id))
+                .filter(|e| roots.iter().any(|root| e.mount_point.starts_with(root)))
+                .map(|e| TrackedMount {
+                    mnt_id: e.mnt_id,
+                    target: e.mount_point.to_string_lossy().to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Magic Mount 可能挂载到的位置：各分区根目录与其工作区
+fn magic_roots(config: &config::Config, tempdir: &Path) -> Vec<PathBuf> {
+    std::iter::once("system")
+        .chain(defs::BUILTIN_PARTITIONS.iter().copied())
+        .chain(config.partitions.iter().map(String::as_str))
+        .map(|p| Path::new("/").join(p))
+        .chain(std::iter::once(tempdir.to_path_buf()))
+        .collect()
+}
+
+/// 执行挂载计划；任一阶段（OverlayFS、Magic Mount、try_umount 登记）失败时回滚本次启动创建的全部挂载
 pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionResult> {
     log::info!(">> Link Start! Robust execution sequence initiated.");
 
     // 全局事务管理器，负责最终的挂载生命周期
     let mut global_tx = MountTransaction::new();
-    
-    // 记录由于 OverlayFS 失败而需要转入 Magic 模式的模块
-    let mut final_magic_ids: HashSet<String> = plan.magic_module_ids.iter().cloned().collect();
-    let mut final_overlay_ids = HashSet::new();
 
-    // 映射表：模块 ID -> 该模块涉及的所有挂载目标 (用于一致性回滚)
-    let mut module_to_targets: HashMap<String, Vec<String>> = HashMap::new();
+    // 执行期间发现的问题（例如导致 OverlayFS 失败的模块）
+    let mut diagnostics: Vec<DiagnosticIssue> = Vec::new();
+
+    match run_phases(plan, config, &mut global_tx, &mut diagnostics) {
+        Ok((overlay_module_ids, magic_module_ids)) => Ok(ExecutionResult {
+            overlay_module_ids,
+            magic_module_ids,
+            rolled_back_mounts: Vec::new(),
+            failure: None,
+            diagnostics,
+        }),
+        Err(e) => {
+            log::error!("{:#}. Rolling back all mounts of this boot.", e);
+
+            Ok(ExecutionResult {
+                overlay_module_ids: Vec::new(),
+                magic_module_ids: Vec::new(),
+                rolled_back_mounts: global_tx.rollback(),
+                failure: Some(format!("{:#}", e)),
+                diagnostics,
+            })
+        }
+    }
+}
+
+/// 依次执行各挂载阶段，返回 (OverlayFS 模块, Magic Mount 模块)
+fn run_phases(
+    plan: &MountPlan,
+    config: &config::Config,
+    global_tx: &mut MountTransaction,
+    diagnostics: &mut Vec<DiagnosticIssue>,
+) -> Result<(Vec<String>, Vec<String>)> {
+    // Magic Mount 需要处理的模块子树，OverlayFS 失败的层会以子树为单位追加进来
+    let mut magic_work = plan.magic_work();
+    let mut final_overlay_ids = HashSet::new();
 
     log::info!(">> Phase 1: Contextual OverlayFS Execution...");
 
     // 1. 尝试执行所有 Overlay 挂载
     for op in &plan.overlay_ops {
-        let involved_modules: Vec<String> = op.lowerdirs.iter()
+        let involved_modules: Vec<String> = op
+            .lowerdirs
+            .iter()
             .filter_map(|p| utils::extract_module_id(p))
             .collect();
 
-        match try_perform_overlay_mount(op, config) {
+        let roots = [PathBuf::from(&op.target)];
+        let mark = global_tx.checkpoint();
+
+        let attempt = global_tx.track(&roots, || {
+            try_perform_overlay_mount(op, &op.lowerdirs, config)
+        });
+
+        match attempt {
             Ok(_) => {
-                global_tx.register(&op.target);
-                // 记录模块与挂载点的关联
-                for id in &involved_modules {
-                    module_to_targets.entry(id.clone()).or_default().push(op.target.clone());
-                    final_overlay_ids.insert(id.clone());
-                }
+                final_overlay_ids.extend(involved_modules);
             }
             Err(e) => {
-                log::warn!("OverlayFS failure at {}: {}. Module-level fallback triggered.", op.target, e);
-                // 该分区涉及的所有模块都必须标记为 Magic 模式
-                for id in involved_modules {
-                    final_magic_ids.insert(id);
-                }
-            }
-        }
-    }
+                // 撤销本次尝试留下的挂载，再按层排查
+                global_tx.rollback_to(mark);
+
+                log::warn!(
+                    "OverlayFS failure at {}: {}. Bisecting lowerdirs to find the culprit...",
+                    op.target,
+                    e
+                );
+
+                let culprits = isolate_faulty_layers(&op.lowerdirs, &mut |layers| {
+                    probe_overlay(op, layers, config)
+                });
+                let survivors: Vec<PathBuf> = op
+                    .lowerdirs
+                    .iter()
+                    .filter(|layer| !culprits.iter().any(|(c, _)| c == *layer))
+                    .cloned()
+                    .collect();
 
-    // 2. 一致性检查：如果一个模块被标记为 Magic，撤销它所有已成功的 Overlay 挂载
-    // 这是为了解决“半 Overlay, 半 Magic”的问题
-    let mut inconsistent_targets = Vec::new();
-    final_overlay_ids.retain(|id| {
-        if final_magic_ids.contains(id) {
-            if let Some(targets) = module_to_targets.remove(id) {
-                inconsistent_targets.extend(targets);
+                let recovered = !culprits.is_empty()
+                    && (survivors.is_empty()
+                        || global_tx
+                            .track(&roots, || try_perform_overlay_mount(op, &survivors, config))
+                            .is_ok());
+
+                if recovered {
+                    final_overlay_ids.extend(
+                        survivors
+                            .iter()
+                            .filter_map(|layer| utils::extract_module_id(layer)),
+                    );
+                    for (layer, err) in culprits {
+                        let id =
+                            utils::extract_module_id(&layer).unwrap_or_else(|| "UNKNOWN".into());
+                        log::warn!(
+                            "Culprit at {}: {} ({}). Moving it to Magic Mount.",
+                            op.target,
+                            id,
+                            err
+                        );
+                        diagnostics.push(DiagnosticIssue {
+                            level: DiagnosticLevel::Warning,
+                            context: id.clone(),
+                            message: format!(
+                                "OverlayFS rejected layer {} on {}: {}",
+                                layer.display(),
+                                op.target,
+                                err
+                            ),
+                        });
+                        move_layer_to_magic(&layer, plan, &mut magic_work);
+                    }
+                } else {
+                    global_tx.rollback_to(mark);
+
+                    log::warn!(
+                        "No single culprit found at {}. Target-level fallback triggered.",
+                        op.target
+                    );
+                    diagnostics.push(DiagnosticIssue {
+                        level: DiagnosticLevel::Warning,
+                        context: op.partition_name.clone(),
+                        message: format!(
+                            "OverlayFS failed on {} and no single layer could be isolated: {:#}",
+                            op.target, e
+                        ),
+                    });
+                    // 该挂载点涉及的所有层都改由 Magic Mount 处理，模块的其他部分不受影响
+                    for layer in &op.lowerdirs {
+                        move_layer_to_magic(layer, plan, &mut magic_work);
+                    }
+                }
             }
-            false // 从 Overlay 列表移除
-        } else {
-            true
-        }
-    });
-
-    if !inconsistent_targets.is_empty() {
-        inconsistent_targets.sort();
-        inconsistent_targets.dedup();
-        for target in inconsistent_targets {
-            log::info!("Cleaning up inconsistent overlay mount: {}", target);
-            let _ = crate::try_umount::send_umountable(&target);
         }
     }
 
-    // 3. 执行 Magic Mount (Phase 2)
-    let mut magic_queue: Vec<String> = final_magic_ids.iter().cloned().collect();
-    magic_queue.sort();
+    // 2. 执行 Magic Mount (Phase 2)
+    // 同一模块可以一部分走 OverlayFS、一部分走 Magic Mount，两者按子树划分互不重叠
+    magic_work.retain(|_, work| !work.paths.is_empty());
 
-    if !magic_queue.is_empty() {
-        let tempdir = PathBuf::from(&config.hybrid_mnt_dir).join("magic_workspace");
-        log::info!(">> Phase 2: Magic Mount Execution (Fallback/Native) at {}", tempdir.display());
+    if !magic_work.is_empty() {
+        let tempdir = PathBuf::from(&config.hybrid_mnt_dir).join(defs::MAGIC_WORKSPACE_DIR);
+        log::info!(
+            ">> Phase 2: Magic Mount Execution (Fallback/Native) at {}",
+            tempdir.display()
+        );
 
         if !tempdir.exists() {
             std::fs::create_dir_all(&tempdir).context("Failed to create magic workspace")?;
         }
 
         let module_dir = Path::new(&config.hybrid_mnt_dir);
-        let magic_need_set: HashSet<String> = magic_queue.iter().cloned().collect();
-
-        // Magic Mount 失败现在被视为严重错误，会中断流程
-        crate::mount::magic_mount::magic_mount(
-            &tempdir,
-            module_dir,
-            &config.mountsource,
-            &config.partitions,
-            magic_need_set,
-            !config.disable_umount,
-        ).context("Critical failure during Magic Mount phase")?;
-        
+
+        global_tx
+            .track(&magic_roots(config, &tempdir), || {
+                crate::mount::magic_mount::magic_mount(
+                    &tempdir,
+                    module_dir,
+                    &config.mountsource,
+                    &config.partitions,
+                    &magic_work,
+                    !config.disable_umount,
+                )
+            })
+            .context("Critical failure during Magic Mount phase")?;
+
         let _ = crate::try_umount::TMPFS.set(tempdir.to_string_lossy().to_string());
     }
 
-    // 4. 提交卸载任务
+    // 3. 提交卸载任务
     #[cfg(any(target_os = "linux", target_os = "android"))]
     if !config.disable_umount {
-        crate::try_umount::commit().map_err(|e| {
-            log::error!("Final try_umount commit failed: {}", e);
-            e
-        })?;
+        global_tx
+            .commit_umounts()
+            .context("Final try_umount commit failed")?;
     }
 
     let mut result_overlay: Vec<String> = final_overlay_ids.into_iter().collect();
-    let mut result_magic: Vec<String> = final_magic_ids.into_iter().collect();
+    let mut result_magic: Vec<String> = magic_work.into_keys().collect();
     result_overlay.sort();
     result_magic.sort();
 
-    Ok(ExecutionResult {
-        overlay_module_ids: result_overlay,
-        magic_module_ids: result_magic,
-    })
+    Ok((result_overlay, result_magic))
+}
+
+/// 将被 OverlayFS 拒绝的层按其在模块内的子树转交 Magic Mount
+fn move_layer_to_magic(
+    layer: &Path,
+    plan: &MountPlan,
+    magic_work: &mut HashMap<String, MagicWork>,
+) {
+    let Some((id, sub_path)) = utils::split_module_path(layer) else {
+        log::warn!("Cannot locate module of layer {}", layer.display());
+        return;
+    };
+
+    magic_work
+        .entry(id.clone())
+        .or_insert_with(|| plan.new_magic_work(&id))
+        .paths
+        .insert(sub_path);
 }
 
 /// 内部辅助函数：执行具体的 Overlay 挂载
-fn try_perform_overlay_mount(op: &OverlayOperation, config: &config::Config) -> Result<()> {
-    let lowerdir_strings: Vec<String> = op.lowerdirs.iter()
-        .map(|p| p.display().to_string())
-        .collect();
+fn try_perform_overlay_mount(
+    op: &OverlayOperation,
+    lowerdirs: &[PathBuf],
+    config: &config::Config,
+) -> Result<()> {
+    let lowerdir_strings: Vec<String> = lowerdirs.iter().map(|p| p.display().to_string()).collect();
 
     let rw_root = Path::new(defs::SYSTEM_RW_DIR);
     let part_rw = rw_root.join(&op.partition_name);
@@ -177,7 +417,11 @@ fn try_perform_overlay_mount(op: &OverlayOperation, config: &config::Config) ->
         (None, None)
     };
 
-    log::info!("Mounting {} [OVERLAY] ({} layers)", op.target, lowerdir_strings.len());
+    log::info!(
+        "Mounting {} [OVERLAY] ({} layers)",
+        op.target,
+        lowerdir_strings.len()
+    );
 
     overlayfs::overlayfs::mount_overlay(
         &op.target,
@@ -185,7 +429,8 @@ fn try_perform_overlay_mount(op: &OverlayOperation, config: &config::Config) ->
         work_opt,
         upper_opt,
         &config.mountsource,
-    ).map_err(|e| anyhow::anyhow!(e))?;
+    )
+    .map_err(|e| anyhow::anyhow!(e))?;
 
     #[cfg(any(target_os = "linux", target_os = "android"))]
     if !config.disable_umount {
@@ -194,3 +439,113 @@ fn try_perform_overlay_mount(op: &OverlayOperation, config: &config::Config) ->
 
     Ok(())
 }
+
+/// 在独立的探测挂载点上尝试挂载给定的 lowerdir 子集，不影响真实目标
+fn probe_overlay(
+    op: &OverlayOperation,
+    lowerdirs: &[PathBuf],
+    config: &config::Config,
+) -> Result<()> {
+    let probe_dir = Path::new(defs::RUN_DIR).join("overlay_probe");
+    utils::ensure_dir_exists(&probe_dir)?;
+
+    let lowerdir_strings: Vec<String> = lowerdirs.iter().map(|p| p.display().to_string()).collect();
+
+    overlayfs::overlayfs::mount_overlayfs(
+        &lowerdir_strings,
+        &op.target,
+        None,
+        None,
+        &probe_dir,
+        &config.mountsource,
+    )?;
+
+    if let Err(e) = unmount(&probe_dir, UnmountFlags::DETACH) {
+        log::warn!(
+            "Failed to detach overlay probe {}: {}",
+            probe_dir.display(),
+            e
+        );
+    }
+
+    Ok(())
+}
+
+/// 二分查找导致挂载失败的 lowerdir，返回 (层路径, 内核错误)
+/// probe 在不影响真实目标的前提下尝试挂载给定的层子集
+/// 若失败只在多个层组合时出现，则返回空列表
+fn isolate_faulty_layers<F>(lowerdirs: &[PathBuf], probe: &mut F) -> Vec<(PathBuf, String)>
+where
+    F: FnMut(&[PathBuf]) -> Result<()>,
+{
+    if lowerdirs.is_empty() {
+        return Vec::new();
+    }
+
+    match probe(lowerdirs) {
+        Ok(()) => Vec::new(),
+        Err(e) if lowerdirs.len() == 1 => vec![(lowerdirs[0].clone(), format!("{:#}", e))],
+        Err(_) => {
+            let (left, right) = lowerdirs.split_at(lowerdirs.len() / 2);
+            let mut culprits = isolate_faulty_layers(left, probe);
+            culprits.extend(isolate_faulty_layers(right, probe));
+            culprits
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    /// 含有任一坏层时失败的探测
+    fn reject(bad: &[&str]) -> impl FnMut(&[PathBuf]) -> Result<()> {
+        let bad = layers(bad);
+        move |lowerdirs| match lowerdirs.iter().find(|l| bad.contains(l)) {
+            Some(layer) => anyhow::bail!("bad layer {}", layer.display()),
+            None => Ok(()),
+        }
+    }
+
+    fn culprits(found: Vec<(PathBuf, String)>) -> Vec<PathBuf> {
+        found.into_iter().map(|(layer, _)| layer).collect()
+    }
+
+    #[test]
+    fn single_culprit_is_isolated() {
+        let found = isolate_faulty_layers(&layers(&["a", "b", "c", "d", "e"]), &mut reject(&["c"]));
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, PathBuf::from("c"));
+        assert!(found[0].1.contains("bad layer c"));
+    }
+
+    #[test]
+    fn every_culprit_is_isolated() {
+        let found = isolate_faulty_layers(
+            &layers(&["a", "b", "c", "d", "e"]),
+            &mut reject(&["a", "d", "e"]),
+        );
+
+        assert_eq!(culprits(found), layers(&["a", "d", "e"]));
+    }
+
+    #[test]
+    fn failure_only_in_combination_isolates_nothing() {
+        // 单独挂载都成功，只有 a 与 d 同时出现时失败
+        let mut probe = |lowerdirs: &[PathBuf]| {
+            if lowerdirs.contains(&PathBuf::from("a")) && lowerdirs.contains(&PathBuf::from("d")) {
+                anyhow::bail!("a conflicts with d");
+            }
+            Ok(())
+        };
+
+        let found = isolate_faulty_layers(&layers(&["a", "b", "c", "d"]), &mut probe);
+
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use crate::{
+    conf::config::Config,
+    core::{inventory, modules::ModuleFile, planner},
+    mount::{
+        magic_mount::utils::collect_module_files,
+        node::{Node, NodeFileType},
+    },
+    utils,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Provider {
+    pub module_id: String,
+    pub source: PathBuf,
+    pub mode: String,
+    pub is_whiteout: bool,
+    pub in_replace_dir: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathExplanation {
+    pub path: String,
+    pub mode: Option<String>,
+    pub overlay_target: Option<String>,
+    pub winner: Option<Provider>,
+    pub shadowed: Vec<Provider>,
+}
+
+/// 解释某个系统路径由哪个模块提供
+pub fn explain(config: &Config, path: &Path) -> Result<PathExplanation> {
+    if !path.is_absolute() {
+        bail!("Path must be absolute: {}", path.display());
+    }
+
+    let path = normalize(path);
+    let modules = inventory::scan(&config.moduledir, config)?;
+    let plan = planner::generate(config, &modules, &config.moduledir)?;
+
+    let mut explanation = PathExplanation {
+        path: path.to_string_lossy().to_string(),
+        mode: None,
+        overlay_target: None,
+        winner: None,
+        shadowed: Vec::new(),
+    };
+
+    // Magic Mount 在 OverlayFS 之后执行，其绑定挂载位于最上层
+    let magic = explain_magic(config, &plan, &path)?;
+    let overlay = explain_overlay(&plan, &path, &mut explanation.overlay_target)?;
+
+    let mut providers = magic.into_iter().chain(overlay);
+    explanation.winner = providers.next();
+    explanation.shadowed = providers.collect();
+    explanation.mode = explanation.winner.as_ref().map(|p| p.mode.clone());
+
+    Ok(explanation)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => out.push(c),
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// 按 lowerdir 顺序（靠前者优先）列出包含该路径的 Overlay 层
+/// 某层删除了该路径或其上级目录不透明时，更低的层不可见，该层即为胜出者
+fn explain_overlay(
+    plan: &planner::MountPlan,
+    path: &Path,
+    overlay_target: &mut Option<String>,
+) -> Result<Vec<Provider>> {
+    // 取最深的匹配目标，嵌套挂载会遮蔽外层
+    let Some(op) = plan
+        .overlay_ops
+        .iter()
+        .filter(|op| path.starts_with(&op.target))
+        .max_by_key(|op| op.target.len())
+    else {
+        return Ok(Vec::new());
+    };
+
+    *overlay_target = Some(op.target.clone());
+    let relative = path.strip_prefix(&op.target)?;
+
+    let mut providers = Vec::new();
+    for layer in &op.lowerdirs {
+        let Some(provider) = probe_layer(layer, relative, "overlay") else {
+            continue;
+        };
+
+        let hides_lower = provider.is_whiteout || provider.in_replace_dir;
+        providers.push(provider);
+        if hides_lower {
+            break;
+        }
+    }
+
+    Ok(providers)
+}
+
+/// 探测单层对路径的影响：路径存在于该层，或该层中的上级目录不透明/被删除从而遮住更低的层
+fn probe_layer(layer: &Path, relative: &Path, mode: &str) -> Option<Provider> {
+    let file = ModuleFile::new(layer, relative).ok();
+
+    let mut hiding_dir = None;
+    let mut ancestor = relative.parent();
+    while let Some(dir) = ancestor {
+        if dir.as_os_str().is_empty() {
+            break;
+        }
+        if let Ok(f) = ModuleFile::new(layer, dir)
+            && (f.is_replace || f.is_whiteout)
+        {
+            hiding_dir = Some(f);
+            break;
+        }
+        ancestor = dir.parent();
+    }
+
+    let (source, is_whiteout, in_replace_dir) = match (file, hiding_dir) {
+        (Some(file), hiding_dir) => (
+            file.real_path,
+            file.is_whiteout,
+            hiding_dir.is_some() || file.is_replace,
+        ),
+        // 路径不在该层中，但其上级目录在该层被替换或删除
+        (None, Some(dir)) => (dir.real_path, dir.is_whiteout, dir.is_replace),
+        (None, None) => return None,
+    };
+
+    Some(Provider {
+        module_id: utils::extract_module_id(&source).unwrap_or_else(|| "UNKNOWN".into()),
+        source,
+        mode: mode.to_string(),
+        is_whiteout,
+        in_replace_dir,
+    })
+}
+
+/// 在 Magic Mount 节点树中查找路径，胜出者为合并树中的节点
+fn explain_magic(config: &Config, plan: &planner::MountPlan, path: &Path) -> Result<Vec<Provider>> {
+    if plan.magic_module_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let need = plan.magic_work();
+    let Some(root) = collect_module_files(&config.moduledir, &config.partitions, &need)? else {
+        return Ok(Vec::new());
+    };
+
+    let Some(winner) = find_node(&root, path) else {
+        return Ok(Vec::new());
+    };
+
+    let mut providers = vec![winner];
+
+    for id in &plan.magic_module_ids {
+        if providers[0].module_id == *id {
+            continue;
+        }
+        let single = HashMap::from([(id.clone(), need[id].clone())]);
+        if let Some(tree) = collect_module_files(&config.moduledir, &config.partitions, &single)?
+            && let Some(provider) = find_node(&tree, path)
+        {
+            providers.push(provider);
+        }
+    }
+
+    Ok(providers)
+}
+
+fn find_node(root: &Node, path: &Path) -> Option<Provider> {
+    let relative = path.strip_prefix("/").ok()?;
+
+    walk_node(root, relative).or_else(|| {
+        // 未独立挂载的分区仍位于 system 节点之下
+        if relative.starts_with("system") {
+            None
+        } else {
+            walk_node(root, &Path::new("system").join(relative))
+        }
+    })
+}
+
+fn walk_node(root: &Node, relative: &Path) -> Option<Provider> {
+    let mut current = root;
+    let mut in_replace_dir = false;
+
+    for component in relative.components() {
+        in_replace_dir |= current.replace;
+        let name = component.as_os_str().to_string_lossy();
+        current = current.children.get(name.as_ref())?;
+    }
+
+    let source = current.module_path.clone()?;
+
+    Some(Provider {
+        module_id: utils::extract_module_id(&source).unwrap_or_else(|| "UNKNOWN".into()),
+        source,
+        mode: "magic".to_string(),
+        is_whiteout: current.file_type == NodeFileType::Whiteout,
+        in_replace_dir: in_replace_dir || current.replace,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    fn layers(modules: &[&str]) -> (TempDir, Vec<PathBuf>) {
+        let root = TempDir::new("explain");
+        let dirs = modules
+            .iter()
+            .map(|m| {
+                let dir = root.path().join(m).join("system");
+                fs::create_dir_all(dir.join("app")).unwrap();
+                dir
+            })
+            .collect();
+        (root, dirs)
+    }
+
+    fn explain_in(lowerdirs: Vec<PathBuf>, path: &str) -> Vec<Provider> {
+        let plan = planner::MountPlan {
+            overlay_ops: vec![planner::OverlayOperation {
+                partition_name: "system".to_string(),
+                target: "/system".to_string(),
+                lowerdirs,
+            }],
+            ..Default::default()
+        };
+        explain_overlay(&plan, Path::new(path), &mut None).unwrap()
+    }
+
+    #[test]
+    fn every_layer_with_the_file_is_listed_in_order() {
+        let (_root, dirs) = layers(&["high", "low"]);
+        for dir in &dirs {
+            fs::write(dir.join("app/a.apk"), "").unwrap();
+        }
+
+        let providers = explain_in(dirs.clone(), "/system/app/a.apk");
+
+        let sources: Vec<_> = providers.iter().map(|p| p.source.clone()).collect();
+        assert_eq!(
+            sources,
+            vec![dirs[0].join("app/a.apk"), dirs[1].join("app/a.apk")]
+        );
+    }
+
+    #[test]
+    fn opaque_parent_in_higher_layer_hides_lower_layers() {
+        let (_root, dirs) = layers(&["high", "low"]);
+        fs::write(dirs[0].join("app/.replace"), "").unwrap();
+        fs::write(dirs[1].join("app/a.apk"), "").unwrap();
+
+        let providers = explain_in(dirs.clone(), "/system/app/a.apk");
+
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].source, dirs[0].join("app"));
+        assert!(providers[0].in_replace_dir);
+    }
+
+    #[test]
+    fn file_inside_opaque_parent_wins_and_stops_the_search() {
+        let (_root, dirs) = layers(&["high", "low"]);
+        fs::write(dirs[0].join("app/.replace"), "").unwrap();
+        fs::write(dirs[0].join("app/a.apk"), "").unwrap();
+        fs::write(dirs[1].join("app/a.apk"), "").unwrap();
+
+        let providers = explain_in(dirs.clone(), "/system/app/a.apk");
+
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].source, dirs[0].join("app/a.apk"));
+        assert!(providers[0].in_replace_dir);
+    }
+
+    #[test]
+    fn whiteout_in_higher_layer_wins() {
+        use nix::sys::stat::{Mode, SFlag, mknod};
+
+        let (_root, dirs) = layers(&["high", "low"]);
+        fs::write(dirs[1].join("app/a.apk"), "").unwrap();
+        // 创建 whiteout 设备节点需要 CAP_MKNOD，无权限时跳过
+        if mknod(&dirs[0].join("app/a.apk"), SFlag::S_IFCHR, Mode::empty(), 0).is_err() {
+            return;
+        }
+
+        let providers = explain_in(dirs.clone(), "/system/app/a.apk");
+
+        assert_eq!(providers.len(), 1);
+        assert!(providers[0].is_whiteout);
+        assert_eq!(providers[0].source, dirs[0].join("app/a.apk"));
+    }
+}
@@ -5,15 +5,18 @@ use std::{
     fs,
     io::Write,
     path::Path,
-    time::{SystemTime, UNIX_EPOCH},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{conf::config::Config, defs, utils};
+use crate::{conf::config::Config, core::rescue, defs, props::PropertySource, utils};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Granary snapshot of the config and the last runtime state
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct Snapshot {
     pub id: String,
     pub timestamp: u64,
@@ -46,41 +49,47 @@ pub fn ensure_recovery_state() -> Result<RecoveryStatus> {
 
     count += 1;
 
-    {
-        let mut file = fs::File::create(path).context("Failed to open boot counter for writing")?;
-        write!(file, "{}", count)?;
-        file.sync_all()
-            .context("Failed to sync boot counter to disk")?;
-    }
+    write_counter(count)?;
 
     log::info!(">> Recovery Protocol: Boot counter at {}", count);
 
+    // 救援二分进行中：计数器未被重置说明上一次测试启动失败
+    if rescue::is_active() {
+        if count >= 2 {
+            log::error!(">> Rescue: previous test boot failed.");
+            let verdict = rescue::on_failed_boot()?;
+            finish_rescue_step(verdict)?;
+        }
+        return Ok(RecoveryStatus::Standby);
+    }
+
     if count >= 3 {
         log::error!(">> RECOVERY TRIGGERED: Detected potential bootloop (3 failed boots).");
+
+        if rescue::verdict_pending() {
+            log::error!(">> Rescue verdict did not recover the system. Disabling all modules.");
+            disable_all_modules()?;
+            let _ = fs::remove_file(path);
+            return Ok(RecoveryStatus::Standby);
+        }
+
         log::warn!(">> Executing emergency rollback from Backups...");
 
         match restore_latest_snapshot() {
             Ok(snapshot_id) => {
                 log::info!(">> Rollback successful. Resetting counter.");
                 let _ = fs::remove_file(path);
-                let notice = format!(
+                write_rescue_notice(&format!(
                     "System recovered from bootloop by restoring snapshot: {}",
                     snapshot_id
-                );
-
-                if let Err(e) = fs::write(RECOVERY_RESCUE_NOTICE, notice) {
-                    log::warn!("Failed to write rescue notice: {}", e);
-                }
+                ));
 
                 return Ok(RecoveryStatus::Restored);
             }
             Err(e) => {
-                log::error!(
-                    ">> Rollback failed: {}. Disabling all modules as last resort.",
-                    e
-                );
-                disable_all_modules()?;
-                let _ = fs::remove_file(path);
+                log::error!(">> Rollback failed: {}. Bisecting modules across boots.", e);
+                let verdict = rescue::start()?;
+                finish_rescue_step(verdict)?;
             }
         }
     }
@@ -88,6 +97,63 @@ pub fn ensure_recovery_state() -> Result<RecoveryStatus> {
     Ok(RecoveryStatus::Standby)
 }
 
+/// 救援步骤完成后，把计数器置为 1 以便下次启动判断本次测试启动的结果
+fn finish_rescue_step(verdict: Option<String>) -> Result<()> {
+    if let Some(verdict) = verdict {
+        write_rescue_notice(&verdict);
+    }
+
+    write_counter(1)
+}
+
+fn write_counter(count: u8) -> Result<()> {
+    let mut file = fs::File::create(RECOVERY_COUNTER_FILE)
+        .context("Failed to open boot counter for writing")?;
+    write!(file, "{}", count)?;
+    file.sync_all()
+        .context("Failed to sync boot counter to disk")?;
+    Ok(())
+}
+
+fn write_rescue_notice(notice: &str) {
+    if let Err(e) = fs::write(RECOVERY_RESCUE_NOTICE, notice) {
+        log::warn!("Failed to write rescue notice: {}", e);
+    }
+}
+
+/// 等待 sys.boot_completed 后再重置启动计数器
+/// 超时则保留计数器，让下次启动的 Recovery Protocol 感知失败
+pub fn watch_boot_completed(
+    props: &dyn PropertySource,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> bool {
+    let start = Instant::now();
+
+    log::info!(
+        ">> Boot Watch: waiting up to {}s for sys.boot_completed...",
+        timeout.as_secs()
+    );
+
+    loop {
+        if props.get("sys.boot_completed").as_deref() == Some("1") {
+            log::info!(
+                ">> Boot Watch: boot completed after {}s.",
+                start.elapsed().as_secs()
+            );
+            reset_recovery_state();
+            return true;
+        }
+
+        if start.elapsed() >= timeout {
+            log::warn!(">> Boot Watch: timed out. Keeping boot counter for Recovery Protocol.");
+            return false;
+        }
+
+        thread::sleep(poll_interval);
+    }
+}
+
 pub fn reset_recovery_state() {
     let path = Path::new(RECOVERY_COUNTER_FILE);
 
@@ -98,6 +164,12 @@ pub fn reset_recovery_state() {
             log::debug!("Recovery Protocol: Counter reset. Boot successful.");
         }
     }
+
+    match rescue::on_boot_success() {
+        Ok(Some(verdict)) => write_rescue_notice(&verdict),
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed to update rescue state: {}", e),
+    }
 }
 
 pub fn create_snapshot(config: &Config, label: &str, reason: &str) -> Result<String> {
@@ -256,6 +328,9 @@ fn disable_all_modules() -> Result<()> {
     if modules_dir.exists() {
         for entry in fs::read_dir(modules_dir)? {
             let entry = entry?;
+            if entry.file_name() == "meta-hybrid" {
+                continue;
+            }
             let disable_path = entry.path().join("disable");
             if !disable_path.exists() {
                 fs::File::create(disable_path)?;
@@ -2,18 +2,24 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
 };
 
 use anyhow::Result;
 use rayon::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{conf::config, defs};
+use crate::{
+    conf::config,
+    core::planner::{DiagnosticIssue, DiagnosticLevel},
+    defs, utils,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum MountMode {
     #[default]
@@ -22,12 +28,27 @@ pub enum MountMode {
     Ignore,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// Per-module mount rules read from hybrid_rules.json
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct ModuleRules {
+    /// Mode for paths without a specific rule
     #[serde(default)]
     pub default_mode: MountMode,
+    /// Mode overrides keyed by path inside the module, globs allowed, the most specific match wins
     #[serde(default)]
     pub paths: HashMap<String, MountMode>,
+    /// Modules with a higher priority win conflicts, `module_order` in the config takes precedence
+    #[serde(default)]
+    pub priority: i32,
+    /// Module IDs or provided names that must be enabled for this module to mount
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Module IDs or provided names that cannot be mounted together with this module
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    /// Extra names this module satisfies in `requires` and `conflicts`, besides its own ID
+    #[serde(default)]
+    pub provides: Vec<String>,
 }
 
 impl ModuleRules {
@@ -41,11 +62,21 @@ impl ModuleRules {
             ..Default::default()
         };
 
+        // module.prop 中的 requires=、conflicts=、provides= 以逗号分隔
+        let prop = read_prop_lists(&module_dir.join("module.prop"));
+        rules.requires = prop.requires;
+        rules.conflicts = prop.conflicts;
+        rules.provides = prop.provides;
+
         // Helper struct for partial loading to avoid overwriting defaults with serde defaults
         #[derive(Deserialize)]
         struct PartialRules {
             default_mode: Option<MountMode>,
             paths: Option<HashMap<String, MountMode>>,
+            priority: Option<i32>,
+            requires: Option<Vec<String>>,
+            conflicts: Option<Vec<String>>,
+            provides: Option<Vec<String>>,
         }
 
         let internal_config = module_dir.join("hybrid_rules.json");
@@ -60,6 +91,14 @@ impl ModuleRules {
                         if let Some(paths) = partial.paths {
                             rules.paths = paths;
                         }
+                        if let Some(priority) = partial.priority {
+                            rules.priority = priority;
+                        }
+                        rules.requires.extend(partial.requires.unwrap_or_default());
+                        rules
+                            .conflicts
+                            .extend(partial.conflicts.unwrap_or_default());
+                        rules.provides.extend(partial.provides.unwrap_or_default());
                     }
                     Err(e) => {
                         log::warn!("Failed to parse rules for module '{}': {}", module_id, e)
@@ -83,6 +122,19 @@ impl ModuleRules {
                         if let Some(paths) = user_rules.paths {
                             rules.paths.extend(paths);
                         }
+                        if let Some(priority) = user_rules.priority {
+                            rules.priority = priority;
+                        }
+                        // 用户规则整体替换模块自身的声明，便于解除误报的冲突
+                        if let Some(requires) = user_rules.requires {
+                            rules.requires = requires;
+                        }
+                        if let Some(conflicts) = user_rules.conflicts {
+                            rules.conflicts = conflicts;
+                        }
+                        if let Some(provides) = user_rules.provides {
+                            rules.provides = provides;
+                        }
                     }
                     Err(e) => {
                         log::warn!("Failed to parse user rules for '{}': {}", module_id, e)
@@ -94,15 +146,127 @@ impl ModuleRules {
             }
         }
 
+        for list in [
+            &mut rules.requires,
+            &mut rules.conflicts,
+            &mut rules.provides,
+        ] {
+            list.retain(|name| !name.is_empty());
+            list.sort();
+            list.dedup();
+        }
+
         rules
     }
 
     pub fn get_mode(&self, relative_path: &str) -> MountMode {
-        if let Some(mode) = self.paths.get(relative_path) {
-            return mode.clone();
+        self.rule_for(relative_path)
+            .map(|(_, mode)| mode.clone())
+            .unwrap_or_else(|| self.default_mode.clone())
+    }
+
+    /// 返回作用于该路径的最具体规则；规则同时作用于匹配路径下的所有内容
+    /// 规则键为模块内相对路径，支持 * ? 以及跨层级的 **
+    pub fn rule_for(&self, relative_path: &str) -> Option<(&str, &MountMode)> {
+        let path = split_path(relative_path);
+
+        self.paths
+            .iter()
+            .filter(|(pattern, _)| {
+                let pattern = split_path(pattern);
+                (1..=path.len()).any(|len| glob_match(&pattern, &path[..len]))
+            })
+            .max_by_key(|(pattern, _)| (specificity(pattern), pattern.as_str()))
+            .map(|(pattern, mode)| (pattern.as_str(), mode))
+    }
+
+    /// 该路径之下是否存在模式不同于 mode 的规则，存在时需要继续下钻
+    pub fn has_rules_below(&self, relative_path: &str, mode: &MountMode) -> bool {
+        let path = split_path(relative_path);
+
+        self.paths
+            .iter()
+            .any(|(pattern, m)| m != mode && glob_match_below(&split_path(pattern), &path))
+    }
+}
+
+#[derive(Default)]
+struct PropLists {
+    requires: Vec<String>,
+    conflicts: Vec<String>,
+    provides: Vec<String>,
+}
+
+fn read_prop_lists(path: &Path) -> PropLists {
+    let mut lists = PropLists::default();
+    let Ok(content) = fs::read_to_string(path) else {
+        return lists;
+    };
+
+    for line in content.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let target = match key.trim() {
+            "requires" => &mut lists.requires,
+            "conflicts" => &mut lists.conflicts,
+            "provides" => &mut lists.provides,
+            _ => continue,
+        };
+        target.extend(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string),
+        );
+    }
+
+    lists
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect()
+}
+
+/// 层级越多、字面字符越多的规则越具体
+fn specificity(pattern: &str) -> (usize, usize) {
+    let segments = split_path(pattern);
+    let depth = segments.iter().filter(|s| **s != "**").count();
+    let literal = pattern
+        .chars()
+        .filter(|c| !matches!(c, '*' | '?' | '/'))
+        .count();
+    (depth, literal)
+}
+
+fn glob_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => {
+            glob_match(rest, path) || (!path.is_empty() && glob_match(pattern, &path[1..]))
         }
+        Some((first, rest)) => {
+            !path.is_empty()
+                && utils::wildcard_match(first, path[0])
+                && glob_match(rest, &path[1..])
+        }
+    }
+}
 
-        self.default_mode.clone()
+/// 模式能否匹配 path 之下（不含 path 本身）的某个路径
+fn glob_match_below(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some((&"**", rest)), Some((_, path_rest))) => {
+            glob_match_below(rest, path) || glob_match_below(pattern, path_rest)
+        }
+        (Some((first, rest)), Some((name, path_rest))) => {
+            utils::wildcard_match(first, name) && glob_match_below(rest, path_rest)
+        }
     }
 }
 
@@ -113,9 +277,52 @@ pub struct Module {
     pub rules: ModuleRules,
 }
 
+impl Module {
+    /// 模块自身 ID 加上 provides 中声明的名称
+    fn provided_names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id.as_str()).chain(self.rules.provides.iter().map(String::as_str))
+    }
+
+    fn provides(&self, name: &str) -> bool {
+        self.provided_names().any(|n| n == name)
+    }
+}
+
+/// 因依赖或冲突声明被排除出挂载计划的模块
+#[derive(Debug, Clone)]
+pub struct Excluded {
+    pub module: Module,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Inventory {
+    /// 参与挂载的模块，按优先级从高到低
+    pub modules: Vec<Module>,
+    pub excluded: Vec<Excluded>,
+}
+
+impl Inventory {
+    pub fn diagnostics(&self) -> Vec<DiagnosticIssue> {
+        self.excluded
+            .iter()
+            .map(|e| DiagnosticIssue {
+                level: DiagnosticLevel::Warning,
+                context: e.module.id.clone(),
+                message: format!("Excluded from mount plan: {}", e.reason),
+            })
+            .collect()
+    }
+}
+
 pub fn scan(source_dir: &Path, cfg: &config::Config) -> Result<Vec<Module>> {
+    Ok(scan_inventory(source_dir, cfg)?.modules)
+}
+
+/// 扫描模块并按 requires/conflicts 声明排除无法共存的模块
+pub fn scan_inventory(source_dir: &Path, cfg: &config::Config) -> Result<Inventory> {
     if !source_dir.exists() {
-        return Ok(Vec::new());
+        return Ok(Inventory::default());
     }
 
     let dir_entries = fs::read_dir(source_dir)?.collect::<std::io::Result<Vec<_>>>()?;
@@ -155,7 +362,370 @@ pub fn scan(source_dir: &Path, cfg: &config::Config) -> Result<Vec<Module>> {
         })
         .collect();
 
-    modules.sort_by(|a, b| b.id.cmp(&a.id));
+    sort_by_precedence(&mut modules, cfg);
+
+    Ok(resolve_relations(modules))
+}
+
+/// 按覆盖优先级从高到低排序：module_order 中列出的模块按列出顺序最先，
+/// 其余按 priority 降序，同级时沿用按 ID 逆序的旧行为
+pub fn sort_by_precedence(modules: &mut [Module], cfg: &config::Config) {
+    modules.sort_by_cached_key(|m| {
+        let listed = cfg
+            .module_order
+            .iter()
+            .position(|id| *id == m.id)
+            .unwrap_or(usize::MAX);
+        (listed, Reverse(m.rules.priority), Reverse(m.id.clone()))
+    });
+}
+
+/// 按确定的策略处理模块间的依赖与冲突，modules 须已按优先级排序：
+/// 1. 反复排除 requires 无法由其余模块满足的模块，直到不再变化
+/// 2. 按优先级依次接纳模块，与已接纳模块冲突（任一方声明）的低优先级模块被排除
+/// 3. 冲突排除可能使依赖失效，再执行一次第 1 步
+/// 4. 第 3 步排除了新模块时，它引起的冲突排除可能已不成立，回到第 1 步重新判定
+///
+/// 因依赖缺失被排除的模块只增不减，因此循环必然收敛
+fn resolve_relations(modules: Vec<Module>) -> Inventory {
+    let mut unmet: HashMap<String, String> = HashMap::new();
+
+    let mut reasons = loop {
+        let mut reasons = unmet.clone();
+
+        drop_unmet_requires(&modules, &mut reasons, &mut unmet);
+        admit_by_precedence(&modules, &mut reasons);
+
+        let settled = unmet.len();
+        drop_unmet_requires(&modules, &mut reasons, &mut unmet);
+
+        if unmet.len() == settled {
+            break reasons;
+        }
+    };
+
+    // 循环早期记录的原因可能已不成立（例如被依赖的模块后来又被接纳），按最终结果重新说明
+    let admitted: Vec<&Module> = modules
+        .iter()
+        .filter(|m| !reasons.contains_key(&m.id))
+        .collect();
+    for module in &modules {
+        if let Some(reason) = reasons.get_mut(&module.id)
+            && let Some(explained) = explain_exclusion(module, &modules, &admitted)
+        {
+            *reason = explained;
+        }
+    }
+
+    let mut inventory = Inventory::default();
+    for module in modules {
+        match reasons.remove(&module.id) {
+            Some(reason) => {
+                log::warn!("Module '{}' excluded: {}", module.id, reason);
+                inventory.excluded.push(Excluded { module, reason });
+            }
+            None => inventory.modules.push(module),
+        }
+    }
+
+    inventory
+}
+
+fn admit_by_precedence(modules: &[Module], reasons: &mut HashMap<String, String>) {
+    let mut accepted: Vec<&Module> = Vec::new();
+    for module in modules {
+        if reasons.contains_key(&module.id) {
+            continue;
+        }
+
+        let clash = accepted
+            .iter()
+            .find_map(|kept| conflict_between(kept, module).map(|c| (kept, c)));
+
+        match clash {
+            Some((kept, (declared_by, name))) => {
+                let reason = format!(
+                    "conflicts with higher-precedence module '{}' ('{}' declares conflicts={})",
+                    kept.id, declared_by, name
+                );
+                reasons.insert(module.id.clone(), reason);
+            }
+            None => accepted.push(module),
+        }
+    }
+}
+
+/// 排除依赖无法满足的模块，同时记入 unmet 以便后续轮次沿用
+fn drop_unmet_requires(
+    modules: &[Module],
+    reasons: &mut HashMap<String, String>,
+    unmet: &mut HashMap<String, String>,
+) {
+    loop {
+        let active: Vec<&Module> = modules
+            .iter()
+            .filter(|m| !reasons.contains_key(&m.id))
+            .collect();
+        let available: HashSet<&str> = active.iter().flat_map(|m| m.provided_names()).collect();
+
+        let unmet_require = active.iter().find_map(|m| {
+            m.rules
+                .requires
+                .iter()
+                .find(|name| !available.contains(name.as_str()))
+                .map(|name| (*m, name))
+        });
+
+        let Some((module, name)) = unmet_require else {
+            break;
+        };
+
+        let reason = match modules
+            .iter()
+            .find(|m| reasons.contains_key(&m.id) && m.provides(name))
+        {
+            Some(provider) => format!(
+                "requires '{}', which is only provided by excluded module '{}'",
+                name, provider.id
+            ),
+            None => format!("requires '{}', which no enabled module provides", name),
+        };
+        reasons.insert(module.id.clone(), reason.clone());
+        unmet.insert(module.id.clone(), reason);
+    }
+}
+
+/// 对照最终接纳的模块说明排除原因：先看依赖是否满足，再看与哪个已接纳模块冲突
+fn explain_exclusion(module: &Module, modules: &[Module], admitted: &[&Module]) -> Option<String> {
+    for name in &module.rules.requires {
+        if admitted.iter().any(|m| m.provides(name)) {
+            continue;
+        }
+
+        return Some(
+            match modules
+                .iter()
+                .find(|m| m.id != module.id && m.provides(name))
+            {
+                Some(provider) => format!(
+                    "requires '{}', which is only provided by excluded module '{}'",
+                    name, provider.id
+                ),
+                None => format!("requires '{}', which no enabled module provides", name),
+            },
+        );
+    }
+
+    // 所需名称的提供者与之冲突时，这才是它被排除的根本原因
+    let conflicting_provider = module.rules.requires.iter().find_map(|name| {
+        admitted
+            .iter()
+            .filter(|kept| kept.provides(name))
+            .find_map(|kept| conflict_between(kept, module).map(|c| (name, kept, c)))
+    });
+    if let Some((name, kept, (declared_by, conflict))) = conflicting_provider {
+        return Some(format!(
+            "requires '{}', but its provider '{}' conflicts with it ('{}' declares conflicts={})",
+            name, kept.id, declared_by, conflict
+        ));
+    }
+
+    let rank = |id: &str| modules.iter().position(|m| m.id == id);
+    admitted.iter().find_map(|kept| {
+        let (declared_by, conflict) = conflict_between(kept, module)?;
+
+        Some(if rank(&kept.id) < rank(&module.id) {
+            format!(
+                "conflicts with higher-precedence module '{}' ('{}' declares conflicts={})",
+                kept.id, declared_by, conflict
+            )
+        } else {
+            format!(
+                "conflicts with module '{}' ('{}' declares conflicts={})",
+                kept.id, declared_by, conflict
+            )
+        })
+    })
+}
+
+/// 两个模块是否冲突，返回声明冲突的模块 ID 与被冲突的名称
+fn conflict_between<'a>(a: &'a Module, b: &'a Module) -> Option<(&'a str, &'a str)> {
+    a.rules
+        .conflicts
+        .iter()
+        .find(|name| b.provides(name))
+        .map(|name| (a.id.as_str(), name.as_str()))
+        .or_else(|| {
+            b.rules
+                .conflicts
+                .iter()
+                .find(|name| a.provides(name))
+                .map(|name| (b.id.as_str(), name.as_str()))
+        })
+}
 
-    Ok(modules)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(id: &str, requires: &[&str], conflicts: &[&str]) -> Module {
+        Module {
+            id: id.to_string(),
+            source_path: PathBuf::from("/data/adb/modules").join(id),
+            rules: ModuleRules {
+                requires: requires.iter().map(|s| s.to_string()).collect(),
+                conflicts: conflicts.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn ids(modules: &[Module]) -> Vec<&str> {
+        modules.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn conflict_loser_is_readmitted_when_winner_is_dropped_later() {
+        // A 与 B 冲突而排除 B；C 与 A 冲突被排除，A 又因依赖 C 被排除，B 应重新接纳
+        let inventory = resolve_relations(vec![
+            module("a", &["c"], &["b"]),
+            module("b", &[], &[]),
+            module("c", &[], &["a"]),
+        ]);
+
+        assert_eq!(ids(&inventory.modules), vec!["b", "c"]);
+
+        let excluded: Vec<_> = inventory
+            .excluded
+            .iter()
+            .map(|e| (e.module.id.as_str(), e.reason.as_str()))
+            .collect();
+        assert_eq!(
+            excluded,
+            vec![(
+                "a",
+                "requires 'c', but its provider 'c' conflicts with it ('c' declares conflicts=a)"
+            )]
+        );
+    }
+
+    #[test]
+    fn conflicts_are_resolved_by_precedence() {
+        let inventory = resolve_relations(vec![
+            module("high", &[], &[]),
+            module("low", &[], &["high"]),
+        ]);
+
+        assert_eq!(ids(&inventory.modules), vec!["high"]);
+        assert_eq!(
+            inventory.excluded[0].reason,
+            "conflicts with higher-precedence module 'high' ('low' declares conflicts=high)"
+        );
+    }
+
+    #[test]
+    fn missing_requirement_cascades() {
+        let inventory = resolve_relations(vec![
+            module("app", &["lib"], &[]),
+            module("lib", &["base"], &[]),
+            module("other", &[], &[]),
+        ]);
+
+        assert_eq!(ids(&inventory.modules), vec!["other"]);
+        assert_eq!(
+            ids(&inventory
+                .excluded
+                .iter()
+                .map(|e| e.module.clone())
+                .collect::<Vec<_>>()),
+            vec!["app", "lib"]
+        );
+    }
+
+    fn rules(paths: &[(&str, MountMode)]) -> ModuleRules {
+        ModuleRules {
+            paths: paths
+                .iter()
+                .map(|(pattern, mode)| (pattern.to_string(), mode.clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn glob(pattern: &str, path: &str) -> bool {
+        glob_match(&split_path(pattern), &split_path(path))
+    }
+
+    #[test]
+    fn glob_supports_wildcards_and_double_star() {
+        assert!(glob("system/app/*", "system/app/Foo"));
+        assert!(!glob("system/app/*", "system/app/Foo/Foo.apk"));
+        assert!(glob("system/**/*.apk", "system/app/Foo/Foo.apk"));
+        assert!(glob("system/**/*.apk", "system/Foo.apk"));
+        assert!(glob("**/lib?4", "vendor/lib64"));
+        assert!(!glob("vendor/*", "system/app"));
+        assert!(glob("./system//bin/", "system/bin"));
+    }
+
+    #[test]
+    fn rule_applies_to_everything_below_its_path() {
+        let rules = rules(&[("system/app", MountMode::Magic)]);
+
+        assert_eq!(
+            rules.rule_for("system/app/Foo/Foo.apk"),
+            Some(("system/app", &MountMode::Magic))
+        );
+        assert_eq!(rules.rule_for("system/bin/sh"), None);
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let rules = rules(&[
+            ("system/**", MountMode::Magic),
+            ("system/app/*", MountMode::Ignore),
+            ("system/app/Foo", MountMode::Overlay),
+            ("system/app/F?o", MountMode::Magic),
+        ]);
+
+        assert_eq!(
+            rules.rule_for("system/app/Foo/Foo.apk").map(|(p, _)| p),
+            Some("system/app/Foo")
+        );
+        assert_eq!(
+            rules.rule_for("system/app/Bar").map(|(p, _)| p),
+            Some("system/app/*")
+        );
+        assert_eq!(
+            rules.rule_for("system/etc/hosts").map(|(p, _)| p),
+            Some("system/**")
+        );
+    }
+
+    #[test]
+    fn equally_specific_rules_break_ties_by_pattern() {
+        let rules = rules(&[
+            ("vendor/*", MountMode::Magic),
+            ("ven*/lib", MountMode::Ignore),
+        ]);
+        assert_eq!(specificity("vendor/*"), specificity("ven*/lib"));
+
+        // 层级与字面字符数相同，按模式字符串取较大者，结果与 HashMap 顺序无关
+        assert_eq!(
+            rules.rule_for("vendor/lib").map(|(p, _)| p),
+            Some("vendor/*")
+        );
+    }
+
+    #[test]
+    fn rules_below_require_descending() {
+        let rules = rules(&[
+            ("system", MountMode::Overlay),
+            ("system/app/**/*.apk", MountMode::Magic),
+        ]);
+
+        assert!(rules.has_rules_below("system", &MountMode::Overlay));
+        assert!(rules.has_rules_below("system/app/Foo", &MountMode::Overlay));
+        assert!(!rules.has_rules_below("system/bin", &MountMode::Overlay));
+        assert!(!rules.has_rules_below("system", &MountMode::Magic));
+    }
 }
@@ -0,0 +1,336 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use extattr::{lgetxattr, llistxattr};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::{defs, utils};
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    Special,
+}
+
+/// 单个条目同步时的源端元数据
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Entry {
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    #[serde(default)]
+    pub rdev: u64,
+    /// 普通文件的硬链接数，目录记为 0
+    #[serde(default)]
+    pub nlink: u64,
+    /// 源端设备号与 inode，仅用于本次同步识别硬链接组
+    #[serde(skip)]
+    pub dev: u64,
+    #[serde(skip)]
+    pub ino: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// 扩展属性，值以十六进制保存
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, String>,
+    /// 内容的 SHA-256，仅在需要区分内容时计算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Entry {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let metadata = fs::symlink_metadata(path)?;
+        let ft = metadata.file_type();
+
+        let kind = if ft.is_dir() {
+            EntryKind::Dir
+        } else if ft.is_symlink() {
+            EntryKind::Symlink
+        } else if ft.is_char_device() || ft.is_block_device() || ft.is_fifo() {
+            EntryKind::Special
+        } else {
+            EntryKind::File
+        };
+
+        let target = match kind {
+            EntryKind::Symlink => Some(fs::read_link(path)?.to_string_lossy().to_string()),
+            _ => None,
+        };
+
+        Ok(Self {
+            kind,
+            size: if kind == EntryKind::File {
+                metadata.len()
+            } else {
+                0
+            },
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            rdev: metadata.rdev(),
+            nlink: if kind == EntryKind::File {
+                metadata.nlink()
+            } else {
+                0
+            },
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            target,
+            xattrs: read_xattrs(path),
+            hash: None,
+        })
+    }
+
+    /// 除修改时间与哈希外的元数据是否一致
+    fn same_shape(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.size == other.size
+            && self.mode == other.mode
+            && self.uid == other.uid
+            && self.gid == other.gid
+            && self.rdev == other.rdev
+            && self.nlink == other.nlink
+            && self.target == other.target
+            && self.xattrs == other.xattrs
+    }
+
+    /// 元数据完全一致时视为未变化，不读取内容
+    pub fn unchanged(&self, other: &Self) -> bool {
+        self.same_shape(other) && self.mtime == other.mtime && self.mtime_nsec == other.mtime_nsec
+    }
+
+    /// 仅修改时间不同的普通文件，需比较内容才能确定是否变化
+    pub fn needs_hash(&self, other: &Self) -> bool {
+        self.kind == EntryKind::File && self.same_shape(other)
+    }
+
+    /// 属于多链接组的普通文件，返回其源端 inode 标识
+    pub fn link_key(&self) -> Option<(u64, u64)> {
+        (self.kind == EntryKind::File && self.nlink > 1).then_some((self.dev, self.ino))
+    }
+}
+
+/// 同步副本旁保存的清单，记录上次同步时源端每个条目的状态
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Manifest {
+    pub version: u32,
+    /// 键为模块内相对路径，父目录总排在子条目之前
+    pub entries: BTreeMap<String, Entry>,
+}
+
+impl Manifest {
+    /// 遍历模块源目录建立清单，include 返回 false 的条目连同其子树一起跳过
+    pub fn scan<F>(root: &Path, include: F) -> Result<Self>
+    where
+        F: Fn(&str, fs::FileType) -> bool,
+    {
+        let mut entries = BTreeMap::new();
+
+        let walker = WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| {
+                e.path()
+                    .strip_prefix(root)
+                    .is_ok_and(|r| include(&r.to_string_lossy(), e.file_type()))
+            });
+
+        for entry in walker {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(root)?;
+            if relative == Path::new(defs::SYNC_MANIFEST_FILE_NAME) {
+                continue;
+            }
+
+            entries.insert(
+                relative.to_string_lossy().to_string(),
+                Entry::from_path(entry.path())?,
+            );
+        }
+
+        Ok(Self {
+            version: MANIFEST_VERSION,
+            entries,
+        })
+    }
+
+    /// 读取同步副本中的清单，缺失、损坏或版本不符时返回 None
+    pub fn load(synced_dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(synced_dir.join(defs::SYNC_MANIFEST_FILE_NAME)).ok()?;
+        let manifest: Self = serde_json::from_str(&content).ok()?;
+
+        (manifest.version == MANIFEST_VERSION).then_some(manifest)
+    }
+
+    pub fn save(&self, synced_dir: &Path) -> Result<()> {
+        let json = serde_json::to_string(self).context("Failed to serialize sync manifest")?;
+        utils::atomic_write(synced_dir.join(defs::SYNC_MANIFEST_FILE_NAME), json)
+    }
+
+    pub fn remove(synced_dir: &Path) {
+        let _ = fs::remove_file(synced_dir.join(defs::SYNC_MANIFEST_FILE_NAME));
+    }
+}
+
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn read_xattrs(path: &Path) -> BTreeMap<String, String> {
+    let mut xattrs = BTreeMap::new();
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Ok(names) = llistxattr(path) {
+        for name in names {
+            if let Ok(value) = lgetxattr(path, &name) {
+                xattrs.insert(name.to_string_lossy().to_string(), to_hex(&value));
+            }
+        }
+    }
+
+    xattrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    fn file() -> Entry {
+        Entry {
+            kind: EntryKind::File,
+            size: 4096,
+            mtime: 1_700_000_000,
+            mtime_nsec: 500,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            nlink: 1,
+            dev: 64769,
+            ino: 1234,
+            target: None,
+            xattrs: BTreeMap::new(),
+            hash: None,
+        }
+    }
+
+    #[test]
+    fn identical_metadata_is_unchanged() {
+        let old = file();
+        let mut new = file();
+        // 源端 inode 与已保存的哈希不参与比较
+        new.ino = 99;
+        new.hash = Some("ab".into());
+
+        assert!(new.unchanged(&old));
+        assert!(new.needs_hash(&old));
+    }
+
+    #[test]
+    fn touched_file_needs_hash() {
+        let old = file();
+        let mut new = file();
+        new.mtime_nsec = 501;
+
+        assert!(!new.unchanged(&old));
+        assert!(new.needs_hash(&old));
+    }
+
+    #[test]
+    fn metadata_changes_skip_hashing() {
+        let old = file();
+        let changes: [fn(&mut Entry); 5] = [
+            |e| e.size = 8192,
+            |e| e.mode = 0o100755,
+            |e| e.uid = 1000,
+            |e| e.nlink = 2,
+            |e| {
+                e.xattrs
+                    .insert("security.selinux".into(), "753a6f626a".into());
+            },
+        ];
+
+        for change in changes {
+            let mut new = file();
+            new.mtime += 1;
+            change(&mut new);
+
+            assert!(!new.unchanged(&old));
+            assert!(!new.needs_hash(&old));
+        }
+    }
+
+    #[test]
+    fn only_regular_files_are_hashed() {
+        let mut old = file();
+        old.kind = EntryKind::Symlink;
+        old.target = Some("/system/bin/toybox".into());
+        let mut new = old.clone();
+        new.mtime += 1;
+
+        assert!(!new.unchanged(&old));
+        assert!(!new.needs_hash(&old));
+
+        new.target = Some("/system/bin/busybox".into());
+        assert!(!new.needs_hash(&old));
+    }
+
+    #[test]
+    fn scan_records_entries_and_skips_excluded_subtrees() {
+        let dir = TempDir::new("manifest");
+        let root = dir.path();
+        fs::create_dir_all(root.join("system/bin")).unwrap();
+        fs::create_dir_all(root.join("webroot")).unwrap();
+        fs::write(root.join("system/bin/tool"), "#!/bin/sh\n").unwrap();
+        fs::write(root.join("webroot/index.html"), "").unwrap();
+        fs::write(root.join(defs::SYNC_MANIFEST_FILE_NAME), "{}").unwrap();
+
+        let manifest = Manifest::scan(root, |path, _| path != "webroot").unwrap();
+
+        let paths: Vec<_> = manifest.entries.keys().map(String::as_str).collect();
+        assert_eq!(paths, vec!["system", "system/bin", "system/bin/tool"]);
+        assert_eq!(manifest.entries["system/bin/tool"].size, 10);
+        assert_eq!(manifest.entries["system/bin"].kind, EntryKind::Dir);
+
+        // dev/ino 不落盘，读回后仍应判定为未变化
+        manifest.save(root).unwrap();
+        let loaded = Manifest::load(root).unwrap();
+        assert_eq!(loaded.entries.len(), manifest.entries.len());
+        for (path, entry) in &manifest.entries {
+            assert!(entry.unchanged(&loaded.entries[path]));
+        }
+    }
+}
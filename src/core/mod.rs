@@ -1,19 +1,23 @@
 // Copyright 2026 Hybrid Mount Developers
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod dedup;
 pub mod executor;
+pub mod explain;
 pub mod granary;
 pub mod inventory;
+pub mod manifest;
 pub mod modules;
 pub mod planner;
 pub mod poaceae;
+pub mod rescue;
 pub mod state;
 pub mod storage;
 pub mod sync;
 
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 
 use crate::conf::config::Config;
 
@@ -21,16 +25,24 @@ pub struct Init;
 
 pub struct StorageReady {
     pub handle: storage::StorageHandle,
+    pub modules: Vec<inventory::Module>,
+    /// 扫描与存储预检阶段产生的诊断
+    pub diagnostics: Vec<planner::DiagnosticIssue>,
 }
 
 pub struct ModulesReady {
     pub handle: storage::StorageHandle,
     pub modules: Vec<inventory::Module>,
+    /// 扫描阶段因依赖或冲突排除模块所产生的诊断
+    pub diagnostics: Vec<planner::DiagnosticIssue>,
+    pub sync_stats: sync::SyncStats,
 }
 
 pub struct Planned {
     pub handle: storage::StorageHandle,
     pub modules: Vec<inventory::Module>,
+    pub diagnostics: Vec<planner::DiagnosticIssue>,
+    pub sync_stats: sync::SyncStats,
     pub plan: planner::MountPlan,
 }
 
@@ -38,6 +50,8 @@ pub struct Executed {
     pub handle: storage::StorageHandle,
     #[allow(dead_code)]
     pub modules: Vec<inventory::Module>,
+    pub diagnostics: Vec<planner::DiagnosticIssue>,
+    pub sync_stats: sync::SyncStats,
     pub plan: planner::MountPlan,
     pub result: executor::ExecutionResult,
 }
@@ -53,44 +67,91 @@ impl MountController<Init> {
     }
 
     // 确保这里只接收 mnt_base
+    // 先扫描模块估算所需容量，再据此选择并建立存储后端
     pub fn init_storage(
         self,
         mnt_base: &Path,
     ) -> Result<MountController<StorageReady>> {
+        let inventory = inventory::scan_inventory(&self.config.moduledir, &self.config)?;
+        let mut diagnostics = inventory.diagnostics();
+        let requirement = sync::estimate(&inventory.modules, &self.config);
+
+        let preflight = storage::preflight(mnt_base, &self.config.overlay_mode, requirement)
+            .inspect_err(|e| record_storage_refusal(mnt_base, e))?;
+        diagnostics.extend(preflight.diagnostics);
+
         let handle = storage::setup(
             mnt_base,
-            &self.config.overlay_mode,
+            &preflight.mode,
             &self.config.mountsource,
+            preflight.capacity,
         )?;
 
         log::info!(">> Storage Backend: [{}]", handle.mode.to_uppercase());
 
         Ok(MountController {
             config: self.config,
-            state: StorageReady { handle },
+            state: StorageReady {
+                handle,
+                modules: inventory.modules,
+                diagnostics,
+            },
         })
     }
 }
 
+/// 存储放不下模块时不挂载任何内容，把原因写入运行状态，供 meta-hybrid diagnostics 查看
+fn record_storage_refusal(mnt_base: &Path, error: &anyhow::Error) {
+    let mut state = state::RuntimeState::new(
+        "none".to_string(),
+        mnt_base.to_path_buf(),
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        (0, 0, 0),
+    );
+    state.diagnostics.push(planner::DiagnosticIssue {
+        level: planner::DiagnosticLevel::Critical,
+        context: "storage".to_string(),
+        message: format!("{:#}", error),
+    });
+
+    if let Err(e) = state.save() {
+        log::error!("Failed to save runtime state: {:#}", e);
+    }
+}
+
 impl MountController<StorageReady> {
-    pub fn scan_and_sync(self) -> Result<MountController<ModulesReady>> {
-        let modules = inventory::scan(&self.config.moduledir, &self.config)?;
-
-        // 核心变更：在瞬时模式下，force_sync 永远为 true
-        log::info!(">> Workspace is transient. Performing full module sync...");
-        
-        sync::perform_sync(
-            &modules, 
-            &self.state.handle.mount_point, 
-            true // 强制同步，因为工作区每次都是空的
+    pub fn scan_and_sync(mut self) -> Result<MountController<ModulesReady>> {
+        let modules = self.state.modules;
+        let mut diagnostics = self.state.diagnostics;
+
+        // 瞬时工作区中没有清单，增量同步会自然退化为全量复制
+        let sync_stats = sync::perform_sync(
+            &modules,
+            &self.state.handle.mount_point,
+            &self.config,
+            false,
         )?;
 
-        // 不再提交存储（commit），因为没有持久化镜像需要同步磁盘
+        if self.config.sync.verify {
+            diagnostics.extend(sync::verify(
+                &modules,
+                &self.state.handle.mount_point,
+                &self.config,
+            ));
+        }
+
+        // 仅 EROFS 暂存区需要提交（打包并挂载为只读镜像）
+        self.state.handle.commit(self.config.disable_umount)?;
+
         Ok(MountController {
             config: self.config,
             state: ModulesReady {
                 handle: self.state.handle,
                 modules,
+                diagnostics,
+                sync_stats,
             },
         })
     }
@@ -109,6 +170,8 @@ impl MountController<ModulesReady> {
             state: Planned {
                 handle: self.state.handle,
                 modules: self.state.modules,
+                diagnostics: self.state.diagnostics,
+                sync_stats: self.state.sync_stats,
                 plan,
             },
         })
@@ -126,6 +189,8 @@ impl MountController<Planned> {
             state: Executed {
                 handle: self.state.handle,
                 modules: self.state.modules,
+                diagnostics: self.state.diagnostics,
+                sync_stats: self.state.sync_stats,
                 plan: self.state.plan,
                 result,
             },
@@ -154,7 +219,11 @@ impl MountController<Executed> {
         active_mounts.sort();
         active_mounts.dedup();
 
-        let state = state::RuntimeState::new(
+        if self.state.result.failure.is_some() {
+            active_mounts.clear();
+        }
+
+        let mut state = state::RuntimeState::new(
             self.state.handle.mode,
             self.state.handle.mount_point,
             self.state.result.overlay_module_ids,
@@ -162,12 +231,30 @@ impl MountController<Executed> {
             active_mounts,
             storage_stats,
         );
+        state.rolled_back_mounts = self.state.result.rolled_back_mounts;
+        state.rollback_reason = self.state.result.failure.clone();
+        state.diagnostics = self.state.diagnostics;
+        state.diagnostics.extend(self.state.result.diagnostics);
+        state.sync = self.state.sync_stats;
 
         if let Err(e) = state.save() {
             log::error!("Failed to save runtime state: {:#}", e);
         }
 
-        granary::reset_recovery_state();
+        if let Some(failure) = self.state.result.failure {
+            // 不重置启动计数器，让 Recovery Protocol 感知本次失败
+            bail!(
+                "Mount sequence rolled back ({} mounts): {}",
+                state.rolled_back_mounts.len(),
+                failure
+            );
+        }
+
+        if self.config.boot_watch.enabled {
+            log::info!(">> Boot counter will be reset by boot-watch after sys.boot_completed.");
+        } else {
+            granary::reset_recovery_state();
+        }
 
         log::info!(">> System operational. Mount sequence complete.");
 
@@ -57,7 +57,7 @@ impl From<&Path> for ModuleProp {
 }
 
 #[derive(Serialize)]
-struct ModuleInfo {
+pub struct ModuleInfo {
     id: String,
     name: String,
     version: String,
@@ -66,6 +66,9 @@ struct ModuleInfo {
     mode: String,
     is_mounted: bool,
     rules: inventory::ModuleRules,
+    /// 因依赖或冲突被排除出挂载计划的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excluded: Option<String>,
 }
 
 impl ModuleInfo {
@@ -87,6 +90,7 @@ impl ModuleInfo {
             description: prop.description,
             mode: mode_str.to_string(),
             rules: m.rules,
+            excluded: None,
         }
     }
 }
@@ -144,8 +148,8 @@ impl ModuleFile {
     }
 }
 
-pub fn print_list(config: &Config) -> Result<()> {
-    let modules = inventory::scan(&config.moduledir, config)?;
+pub fn list(config: &Config) -> Result<Vec<ModuleInfo>> {
+    let inventory = inventory::scan_inventory(&config.moduledir, config)?;
 
     let state = RuntimeState::load().unwrap_or_default();
 
@@ -156,12 +160,22 @@ pub fn print_list(config: &Config) -> Result<()> {
         .map(|s| s.as_str())
         .collect();
 
-    let infos: Vec<ModuleInfo> = modules
+    let mut infos: Vec<ModuleInfo> = inventory
+        .modules
         .into_iter()
         .map(|m| ModuleInfo::new(m, &mounted_ids))
         .collect();
 
-    println!("{}", serde_json::to_string(&infos)?);
+    infos.extend(inventory.excluded.into_iter().map(|e| ModuleInfo {
+        excluded: Some(e.reason),
+        ..ModuleInfo::new(e.module, &mounted_ids)
+    }));
+
+    Ok(infos)
+}
+
+pub fn print_list(config: &Config) -> Result<()> {
+    println!("{}", serde_json::to_string(&list(config)?)?);
 
     Ok(())
 }
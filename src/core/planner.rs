@@ -2,44 +2,58 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::Write as _,
     fs,
     path::{Path, PathBuf},
 };
 
 use anyhow::Result;
 use rayon::prelude::*;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
 use crate::{
     conf::config,
-    core::inventory::{Module, MountMode},
+    core::inventory::{Module, ModuleRules, MountMode},
     defs, utils,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct OverlayOperation {
     pub partition_name: String,
     pub target: String,
     pub lowerdirs: Vec<PathBuf>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct MountPlan {
     pub overlay_ops: Vec<OverlayOperation>,
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    /// Why each module in `magic_module_ids` was routed to Magic Mount
+    pub magic_reasons: BTreeMap<String, String>,
+    /// Subtrees of each module handled by Magic Mount, relative to the module root
+    pub magic_paths: BTreeMap<String, BTreeSet<String>>,
+    /// Planned module IDs from highest to lowest precedence
+    pub module_order: Vec<String>,
+    /// Rules of every planned module, consulted again by Magic Mount
+    #[serde(skip)]
+    pub rules: HashMap<String, ModuleRules>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct ConflictEntry {
     pub partition: String,
     pub relative_path: String,
+    /// Modules providing the path, from highest to lowest precedence
     pub contending_modules: Vec<String>,
+    /// Module whose copy is visible
+    pub winner: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum DiagnosticLevel {
     #[allow(dead_code)]
     Info,
@@ -47,13 +61,22 @@ pub enum DiagnosticLevel {
     Critical,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DiagnosticIssue {
     pub level: DiagnosticLevel,
     pub context: String,
     pub message: String,
 }
 
+/// Magic Mount 需要处理的模块子树及其规则
+#[derive(Debug, Clone, Default)]
+pub struct MagicWork {
+    pub rules: ModuleRules,
+    pub paths: BTreeSet<String>,
+    /// 在 module_order 中的位置，越小越优先
+    pub rank: usize,
+}
+
 #[derive(Debug, Default)]
 pub struct AnalysisReport {
     pub conflicts: Vec<ConflictEntry>,
@@ -62,6 +85,31 @@ pub struct AnalysisReport {
 
 #[allow(clippy::collapsible_if)]
 impl MountPlan {
+    pub fn magic_work(&self) -> HashMap<String, MagicWork> {
+        self.magic_paths
+            .iter()
+            .map(|(id, paths)| {
+                let work = MagicWork {
+                    paths: paths.clone(),
+                    ..self.new_magic_work(id)
+                };
+                (id.clone(), work)
+            })
+            .collect()
+    }
+
+    pub fn new_magic_work(&self, module_id: &str) -> MagicWork {
+        MagicWork {
+            rules: self.rules.get(module_id).cloned().unwrap_or_default(),
+            paths: BTreeSet::new(),
+            rank: self
+                .module_order
+                .iter()
+                .position(|id| id == module_id)
+                .unwrap_or(usize::MAX),
+        }
+    }
+
     pub fn analyze(&self) -> AnalysisReport {
         let results: Vec<(Vec<ConflictEntry>, Vec<DiagnosticIssue>)> = self
             .overlay_ops
@@ -116,11 +164,13 @@ impl MountPlan {
                     }
                 }
 
+                // lowerdir 靠前的层位于上方，第一个提供者即为胜出者
                 for (rel_path, modules) in file_map {
                     if modules.len() > 1 {
                         local_conflicts.push(ConflictEntry {
                             partition: op.partition_name.clone(),
                             relative_path: rel_path,
+                            winner: modules[0].clone(),
                             contending_modules: modules,
                         });
                     }
@@ -144,14 +194,136 @@ impl MountPlan {
 
         report
     }
+
+    /// Human readable rendering of the plan, used by the `plan` command
+    pub fn render_tree(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "MountPlan");
+        let _ = writeln!(out, "├── OverlayFS ({} targets)", self.overlay_ops.len());
+        for (i, op) in self.overlay_ops.iter().enumerate() {
+            let last_op = i + 1 == self.overlay_ops.len();
+            let (branch, indent) = if last_op {
+                ("└──", "    ")
+            } else {
+                ("├──", "│   ")
+            };
+            let _ = writeln!(
+                out,
+                "│   {} {} [{}] ({} layers)",
+                branch,
+                op.target,
+                op.partition_name,
+                op.lowerdirs.len()
+            );
+            for (j, layer) in op.lowerdirs.iter().enumerate() {
+                let layer_branch = if j + 1 == op.lowerdirs.len() {
+                    "└──"
+                } else {
+                    "├──"
+                };
+                let module_id = utils::extract_module_id(layer).unwrap_or_else(|| "UNKNOWN".into());
+                let _ = writeln!(
+                    out,
+                    "│   {}{} #{} {} ({})",
+                    indent,
+                    layer_branch,
+                    j + 1,
+                    module_id,
+                    layer.display()
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "├── Overlay Modules ({})",
+            self.overlay_module_ids.len()
+        );
+        for (i, id) in self.overlay_module_ids.iter().enumerate() {
+            let branch = if i + 1 == self.overlay_module_ids.len() {
+                "└──"
+            } else {
+                "├──"
+            };
+            let _ = writeln!(out, "│   {} {}", branch, id);
+        }
+
+        let _ = writeln!(out, "└── Magic Modules ({})", self.magic_module_ids.len());
+        for (i, id) in self.magic_module_ids.iter().enumerate() {
+            let branch = if i + 1 == self.magic_module_ids.len() {
+                "└──"
+            } else {
+                "├──"
+            };
+            match self.magic_reasons.get(id) {
+                Some(reason) => {
+                    let _ = writeln!(out, "    {} {} ({})", branch, id, reason);
+                }
+                None => {
+                    let _ = writeln!(out, "    {} {}", branch, id);
+                }
+            }
+            let indent = if i + 1 == self.magic_module_ids.len() {
+                "    "
+            } else {
+                "│   "
+            };
+            let paths = self.magic_paths.get(id).map(|p| p.len()).unwrap_or(0);
+            for (j, path) in self.magic_paths.get(id).into_iter().flatten().enumerate() {
+                let path_branch = if j + 1 == paths {
+                    "└──"
+                } else {
+                    "├──"
+                };
+                let _ = writeln!(out, "    {}{} {}", indent, path_branch, path);
+            }
+        }
+
+        out
+    }
 }
 
 struct ProcessingItem {
     module_source: PathBuf,
     system_target: PathBuf,
     partition_label: String,
+    /// 模块内相对路径，例如 system/app/Foo，用于匹配规则
+    relative_path: String,
+    /// 父目录是否因下层规则而被拆解
+    split_by_rules: bool,
 }
 
+fn add_magic_reason(reasons: &mut BTreeMap<String, String>, module_id: &str, reason: String) {
+    reasons
+        .entry(module_id.to_string())
+        .and_modify(|r| {
+            if !r.contains(&reason) {
+                r.push_str("; ");
+                r.push_str(&reason);
+            }
+        })
+        .or_insert(reason);
+}
+
+fn rule_reason(rules: &ModuleRules, relative_path: &str) -> String {
+    match rules.rule_for(relative_path) {
+        Some((pattern, _)) if pattern.trim_matches('/') == relative_path => {
+            format!("rule for '{}' is magic", relative_path)
+        }
+        Some((pattern, _)) => format!("rule '{}' is magic for '{}'", pattern, relative_path),
+        None => "default mode is magic".to_string(),
+    }
+}
+
+/// 目录下是否有无法单独作为 OverlayFS 挂载点的文件
+fn has_loose_files(dir: &Path) -> bool {
+    fs::read_dir(dir)
+        .map(|entries| entries.flatten().any(|e| !e.path().is_dir()))
+        .unwrap_or(false)
+}
+
+/// modules 需按覆盖优先级从高到低排列（见 inventory::sort_by_precedence），lowerdir 顺序由此决定
 pub fn generate(
     config: &config::Config,
     modules: &[Module],
@@ -162,7 +334,8 @@ pub fn generate(
     let mut overlay_groups: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
 
     let mut overlay_ids = HashSet::new();
-    let mut magic_ids = HashSet::new();
+    let mut magic_paths: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut magic_reasons: BTreeMap<String, String> = BTreeMap::new();
 
     let sensitive_partitions: HashSet<&str> = defs::SENSITIVE_PARTITIONS.iter().cloned().collect();
 
@@ -190,23 +363,17 @@ pub fn generate(
                     continue;
                 }
 
-                let mode = module.rules.get_mode(&dir_name);
-                if matches!(mode, MountMode::Magic) {
-                    magic_ids.insert(module.id.clone());
-                    // Magic mount logic would go here separately or fallback
-                    continue;
-                }
-                if matches!(mode, MountMode::Ignore) {
-                    continue;
-                }
-
-                overlay_ids.insert(module.id.clone());
+                plan.rules
+                    .entry(module.id.clone())
+                    .or_insert_with(|| module.rules.clone());
 
                 let mut queue = VecDeque::new();
                 queue.push_back(ProcessingItem {
                     module_source: path.clone(),
                     system_target: PathBuf::from("/").join(&dir_name),
                     partition_label: dir_name.clone(),
+                    relative_path: dir_name.clone(),
+                    split_by_rules: false,
                 });
 
                 while let Some(item) = queue.pop_front() {
@@ -214,9 +381,72 @@ pub fn generate(
                         module_source,
                         system_target,
                         partition_label,
+                        relative_path,
+                        split_by_rules,
                     } = item;
 
+                    // 规则在每一层都生效，最具体（最长）的匹配胜出
+                    match module.rules.get_mode(&relative_path) {
+                        MountMode::Magic => {
+                            // 下层有其他规则时同样下钻，只把未被覆盖的部分交给 Magic Mount
+                            // 本层有文件时无法拆解，整体仍走 Magic Mount
+                            if module
+                                .rules
+                                .has_rules_below(&relative_path, &MountMode::Magic)
+                                && !has_loose_files(&module_source)
+                            {
+                                if let Ok(sub_entries) = fs::read_dir(&module_source) {
+                                    for sub_entry in sub_entries.flatten() {
+                                        let sub_path = sub_entry.path();
+                                        if !sub_path.is_dir() {
+                                            continue;
+                                        }
+                                        let sub_name = sub_entry.file_name();
+
+                                        queue.push_back(ProcessingItem {
+                                            module_source: sub_path,
+                                            system_target: system_target.join(&sub_name),
+                                            partition_label: partition_label.clone(),
+                                            relative_path: format!(
+                                                "{}/{}",
+                                                relative_path,
+                                                sub_name.to_string_lossy()
+                                            ),
+                                            split_by_rules: true,
+                                        });
+                                    }
+                                }
+                                continue;
+                            }
+
+                            magic_paths
+                                .entry(module.id.clone())
+                                .or_default()
+                                .insert(relative_path.clone());
+                            add_magic_reason(
+                                &mut magic_reasons,
+                                &module.id,
+                                rule_reason(&module.rules, &relative_path),
+                            );
+                            continue;
+                        }
+                        MountMode::Ignore => continue,
+                        MountMode::Overlay => {}
+                    }
+
                     if !system_target.exists() {
+                        // 父目录已被规则拆解，新增的目录无处挂载 OverlayFS，交给 Magic Mount 创建
+                        if split_by_rules {
+                            magic_paths
+                                .entry(module.id.clone())
+                                .or_default()
+                                .insert(relative_path.clone());
+                            add_magic_reason(
+                                &mut magic_reasons,
+                                &module.id,
+                                format!("'{}' does not exist on the device", relative_path),
+                            );
+                        }
                         continue;
                     }
 
@@ -248,7 +478,29 @@ pub fn generate(
                         .map(|s| s.to_string_lossy())
                         .unwrap_or_default();
 
-                    let should_split = sensitive_partitions.contains(target_name.as_ref())
+                    let split_for_rules = module
+                        .rules
+                        .has_rules_below(&relative_path, &MountMode::Overlay);
+
+                    // 下层规则要求拆解，但本层的文件无法单独作为挂载点，只能整体交给 Magic Mount
+                    if split_for_rules && has_loose_files(&module_source) {
+                        magic_paths
+                            .entry(module.id.clone())
+                            .or_default()
+                            .insert(relative_path.clone());
+                        add_magic_reason(
+                            &mut magic_reasons,
+                            &module.id,
+                            format!(
+                                "files in '{}' sit beside paths with other rules",
+                                relative_path
+                            ),
+                        );
+                        continue;
+                    }
+
+                    let should_split = split_for_rules
+                        || sensitive_partitions.contains(target_name.as_ref())
                         || target_name == "system"; // 总是尝试拆解 /system 以发现内部的软链接
 
                     if should_split {
@@ -264,13 +516,20 @@ pub fn generate(
 
                                 queue.push_back(ProcessingItem {
                                     module_source: sub_path,
-                                    system_target: canonical_target.join(sub_name), // 下钻一层
+                                    system_target: canonical_target.join(&sub_name), // 下钻一层
                                     partition_label: partition_label.clone(),
+                                    relative_path: format!(
+                                        "{}/{}",
+                                        relative_path,
+                                        sub_name.to_string_lossy()
+                                    ),
+                                    split_by_rules: split_for_rules,
                                 });
                             }
                         }
                     } else {
                         // 不需要拆解，直接作为挂载点
+                        overlay_ids.insert(module.id.clone());
                         overlay_groups
                             .entry(canonical_target)
                             .or_default()
@@ -302,10 +561,147 @@ pub fn generate(
         });
     }
 
+    plan.overlay_ops.sort_by(|a, b| a.target.cmp(&b.target));
+
     plan.overlay_module_ids = overlay_ids.into_iter().collect();
-    plan.magic_module_ids = magic_ids.into_iter().collect();
+    plan.magic_module_ids = magic_paths.keys().cloned().collect();
+    plan.module_order = modules
+        .iter()
+        .filter(|m| plan.rules.contains_key(&m.id))
+        .map(|m| m.id.clone())
+        .collect();
     plan.overlay_module_ids.sort();
-    plan.magic_module_ids.sort();
+    plan.magic_reasons = magic_reasons;
+    plan.magic_paths = magic_paths;
 
     Ok(plan)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    /// 以临时目录作为设备上的挂载目标，模块内镜像其绝对路径
+    struct Fixture {
+        root: TempDir,
+        target: TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                root: TempDir::new("planner"),
+                target: TempDir::new("planner-target"),
+            }
+        }
+
+        /// 目标目录在模块内的相对路径，例如 tmp/hybrid-planner-target-1-0
+        fn rel(&self, sub: &str) -> String {
+            let base = self.target.path().to_string_lossy();
+            format!("{}/{}", base.trim_start_matches('/'), sub)
+        }
+
+        fn module_dir(&self, sub: &str) -> PathBuf {
+            self.root.path().join("mod").join(self.rel(sub))
+        }
+
+        fn device_dir(&self, sub: &str) -> PathBuf {
+            self.target.path().join(sub)
+        }
+
+        fn plan(&self, rules: ModuleRules) -> MountPlan {
+            let partition = self.rel("").split('/').next().unwrap().to_string();
+            let config = config::Config {
+                partitions: vec![partition],
+                ..Default::default()
+            };
+            let module = Module {
+                id: "mod".to_string(),
+                source_path: self.root.path().join("mod"),
+                rules,
+            };
+            generate(&config, &[module], &self.root.path().join("storage")).unwrap()
+        }
+    }
+
+    fn rules(default_mode: MountMode, paths: &[(String, MountMode)]) -> ModuleRules {
+        ModuleRules {
+            default_mode,
+            paths: paths.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn overlay_targets(plan: &MountPlan) -> Vec<PathBuf> {
+        plan.overlay_ops
+            .iter()
+            .map(|op| PathBuf::from(&op.target))
+            .collect()
+    }
+
+    #[test]
+    fn new_sibling_of_a_ruled_path_falls_back_to_magic() {
+        let fx = Fixture::new();
+        for sub in ["app/Foo", "app/Bar", "app/NewApp"] {
+            fs::create_dir_all(fx.module_dir(sub)).unwrap();
+        }
+        fs::create_dir_all(fx.device_dir("app/Foo")).unwrap();
+        fs::create_dir_all(fx.device_dir("app/Bar")).unwrap();
+
+        let plan = fx.plan(rules(
+            MountMode::Overlay,
+            &[(fx.rel("app/Foo"), MountMode::Magic)],
+        ));
+
+        let magic = &plan.magic_paths["mod"];
+        assert!(magic.contains(&fx.rel("app/Foo")));
+        assert!(magic.contains(&fx.rel("app/NewApp")));
+        assert!(plan.magic_reasons["mod"].contains("does not exist on the device"));
+        assert_eq!(
+            overlay_targets(&plan),
+            vec![fx.device_dir("app/Bar").canonicalize().unwrap()]
+        );
+    }
+
+    #[test]
+    fn overlay_rule_under_magic_default_is_carved_out() {
+        let fx = Fixture::new();
+        for sub in ["app/Foo", "fonts"] {
+            fs::create_dir_all(fx.module_dir(sub)).unwrap();
+        }
+        fs::create_dir_all(fx.device_dir("fonts")).unwrap();
+
+        let plan = fx.plan(rules(
+            MountMode::Magic,
+            &[(fx.rel("fonts"), MountMode::Overlay)],
+        ));
+
+        assert_eq!(plan.magic_paths["mod"], BTreeSet::from([fx.rel("app")]));
+        assert_eq!(
+            overlay_targets(&plan),
+            vec![fx.device_dir("fonts").canonicalize().unwrap()]
+        );
+    }
+
+    #[test]
+    fn magic_dir_with_files_is_not_split() {
+        let fx = Fixture::new();
+        fs::create_dir_all(fx.module_dir("fonts")).unwrap();
+        fs::write(fx.module_dir("build.prop"), "").unwrap();
+        fs::create_dir_all(fx.device_dir("fonts")).unwrap();
+
+        let plan = fx.plan(rules(
+            MountMode::Magic,
+            &[(fx.rel("fonts"), MountMode::Overlay)],
+        ));
+
+        assert_eq!(
+            plan.magic_paths["mod"],
+            BTreeSet::from([fx.rel("").trim_end_matches('/').to_string()])
+        );
+        assert!(plan.overlay_ops.is_empty());
+    }
+}
@@ -0,0 +1,487 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    os::unix::fs::MetadataExt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    defs,
+    props::{PropFile, PropertySource},
+    utils,
+};
+
+const RESCUE_DIR: &str = "/data/adb/meta-hybrid/rescue";
+const LAST_GOOD_FILE: &str = "/data/adb/meta-hybrid/rescue/last_good.json";
+const SESSION_FILE: &str = "/data/adb/meta-hybrid/rescue/session.json";
+const JOURNAL_FILE: &str = "/data/adb/meta-hybrid/rescue/journal.json";
+const JOURNAL_MAX_ENTRIES: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RescueEvent {
+    Started,
+    Disabled,
+    BootFailed,
+    BootSucceeded,
+    Verdict,
+    Inconclusive,
+    Aborted,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub timestamp: u64,
+    pub step: u32,
+    pub event: RescueEvent,
+    pub modules: Vec<String>,
+    pub message: String,
+}
+
+/// 跨启动保存的二分状态
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RescueSession {
+    pub started_at: u64,
+    pub step: u32,
+    /// 仍可能是元凶的模块
+    pub suspects: Vec<String>,
+    /// 当前由救援模式禁用的模块
+    pub disabled: Vec<String>,
+    /// 已排除嫌疑的模块
+    pub cleared: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RescueReport {
+    pub session: Option<RescueSession>,
+    pub journal: Vec<JournalEntry>,
+}
+
+pub fn is_active() -> bool {
+    Path::new(SESSION_FILE).exists()
+}
+
+pub fn report() -> RescueReport {
+    RescueReport {
+        session: load_json(SESSION_FILE),
+        journal: load_json(JOURNAL_FILE).unwrap_or_default(),
+    }
+}
+
+/// 启动失败且快照恢复无效时开始救援：只怀疑自上次成功启动以来变化的模块
+/// 返回值为最终结论（若已得出）
+pub fn start() -> Result<Option<String>> {
+    let current = enabled_modules();
+    let last_good: Option<BTreeMap<String, String>> = load_json(LAST_GOOD_FILE);
+
+    let changed: Vec<String> = match &last_good {
+        Some(good) => current
+            .iter()
+            .filter(|(id, fp)| good.get(*id) != Some(*fp))
+            .map(|(id, _)| id.clone())
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let (suspects, message) = if changed.is_empty() {
+        (
+            current.keys().cloned().collect::<Vec<_>>(),
+            "No module changes recorded since the last good boot, bisecting all enabled modules"
+                .to_string(),
+        )
+    } else {
+        (
+            changed,
+            "Bisecting modules changed since the last good boot".to_string(),
+        )
+    };
+
+    if suspects.is_empty() {
+        journal(
+            0,
+            RescueEvent::Inconclusive,
+            Vec::new(),
+            "Bootloop detected but no enabled modules to bisect",
+        );
+        return Ok(None);
+    }
+
+    log::warn!(">> Rescue: {} ({} suspects).", message, suspects.len());
+    journal(0, RescueEvent::Started, suspects.clone(), &message);
+
+    let session = RescueSession {
+        started_at: now(),
+        suspects,
+        ..Default::default()
+    };
+
+    advance(session)
+}
+
+/// 上一次测试启动失败：元凶位于仍启用的嫌疑模块中
+pub fn on_failed_boot() -> Result<Option<String>> {
+    let Some(mut session) = load_json::<RescueSession>(SESSION_FILE) else {
+        return Ok(None);
+    };
+
+    narrow(&mut session, false);
+    journal(
+        session.step,
+        RescueEvent::BootFailed,
+        session.suspects.clone(),
+        "Boot failed, culprit is among the modules left enabled",
+    );
+
+    advance(session)
+}
+
+/// 启动成功：若处于救援中，元凶位于被禁用的嫌疑模块中；否则记录当前模块为已知良好
+pub fn on_boot_success() -> Result<Option<String>> {
+    let Some(mut session) = load_json::<RescueSession>(SESSION_FILE) else {
+        if verdict_pending() {
+            journal(
+                0,
+                RescueEvent::BootSucceeded,
+                Vec::new(),
+                "System booted successfully with the rescue verdict applied",
+            );
+        }
+
+        save_json(LAST_GOOD_FILE, &enabled_modules())?;
+        return Ok(None);
+    };
+
+    narrow(&mut session, true);
+    journal(
+        session.step,
+        RescueEvent::BootSucceeded,
+        session.suspects.clone(),
+        "Boot succeeded, culprit is among the disabled modules",
+    );
+
+    advance(session)
+}
+
+/// 放弃当前救援，恢复被救援模式禁用的模块
+pub fn abort() -> Result<()> {
+    let Some(session) = load_json::<RescueSession>(SESSION_FILE) else {
+        return Ok(());
+    };
+
+    for id in &session.disabled {
+        set_disabled(id, false);
+    }
+
+    fs::remove_file(SESSION_FILE).context("Failed to remove rescue session")?;
+    journal(
+        session.step,
+        RescueEvent::Aborted,
+        session.disabled,
+        "Rescue aborted by user, modules re-enabled",
+    );
+
+    Ok(())
+}
+
+/// 救援已得出结论，但之后尚未成功启动过
+pub fn verdict_pending() -> bool {
+    let entries: Vec<JournalEntry> = load_json(JOURNAL_FILE).unwrap_or_default();
+    entries
+        .last()
+        .is_some_and(|e| matches!(e.event, RescueEvent::Verdict | RescueEvent::Inconclusive))
+}
+
+/// 二分的下一步
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    /// 嫌疑模块已全部排除，结果前后矛盾
+    Inconclusive,
+    /// 已缩小到唯一的元凶
+    Verdict(String),
+    /// 下一次测试启动需要禁用的嫌疑模块
+    Disable(BTreeSet<String>),
+}
+
+/// 按测试启动的结果缩小嫌疑范围：成功时元凶在被禁用的模块中，失败时在仍启用的模块中
+fn narrow(session: &mut RescueSession, booted: bool) {
+    let (suspects, cleared): (Vec<String>, Vec<String>) = session
+        .suspects
+        .iter()
+        .cloned()
+        .partition(|id| session.disabled.contains(id) == booted);
+
+    session.cleared.extend(cleared);
+    session.suspects = suspects;
+}
+
+fn plan(suspects: &[String]) -> Step {
+    match suspects {
+        [] => Step::Inconclusive,
+        [culprit] => Step::Verdict(culprit.clone()),
+        suspects => {
+            let half = suspects.len().div_ceil(2);
+            Step::Disable(suspects[..half].iter().cloned().collect())
+        }
+    }
+}
+
+/// 缩小到单个模块时给出结论，否则禁用一半嫌疑模块进入下一次测试启动
+fn advance(mut session: RescueSession) -> Result<Option<String>> {
+    match plan(&session.suspects) {
+        Step::Inconclusive => {
+            for id in &session.disabled {
+                set_disabled(id, false);
+            }
+            fs::remove_file(SESSION_FILE).ok();
+
+            let message = "Bisection is inconsistent, the failure does not follow any single \
+                           suspect module"
+                .to_string();
+            log::error!(">> Rescue: {}", message);
+            journal(
+                session.step,
+                RescueEvent::Inconclusive,
+                session.cleared,
+                &message,
+            );
+            Ok(Some(message))
+        }
+        Step::Verdict(culprit) => {
+            set_disabled(&culprit, true);
+            for id in session.disabled.iter().filter(|id| **id != culprit) {
+                set_disabled(id, false);
+            }
+            fs::remove_file(SESSION_FILE).ok();
+
+            let message = format!(
+                "Module '{}' caused the bootloop and has been disabled",
+                culprit
+            );
+            log::warn!(">> Rescue: {}", message);
+            journal(session.step, RescueEvent::Verdict, vec![culprit], &message);
+            Ok(Some(message))
+        }
+        Step::Disable(next) => {
+            for id in session.disabled.iter().filter(|id| !next.contains(*id)) {
+                set_disabled(id, false);
+            }
+            for id in &next {
+                set_disabled(id, true);
+            }
+
+            session.step += 1;
+            session.disabled = next.into_iter().collect();
+            save_json(SESSION_FILE, &session)?;
+
+            log::warn!(
+                ">> Rescue step {}: disabled {:?}, {} suspects remain.",
+                session.step,
+                session.disabled,
+                session.suspects.len()
+            );
+            journal(
+                session.step,
+                RescueEvent::Disabled,
+                session.disabled.clone(),
+                &format!(
+                    "Disabled {} of {} suspects for the next boot",
+                    session.disabled.len(),
+                    session.suspects.len()
+                ),
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// 已启用模块及其指纹（version、versionCode 与 module.prop 修改时间）
+fn enabled_modules() -> BTreeMap<String, String> {
+    let mut modules = BTreeMap::new();
+
+    let Ok(entries) = fs::read_dir(defs::MODULES_DIR) else {
+        return modules;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let id = entry.file_name().to_string_lossy().to_string();
+
+        if !path.is_dir() || matches!(id.as_str(), "meta-hybrid" | "lost+found") {
+            continue;
+        }
+
+        if path.join(defs::DISABLE_FILE_NAME).exists() || path.join(defs::REMOVE_FILE_NAME).exists()
+        {
+            continue;
+        }
+
+        let prop_path = path.join("module.prop");
+        let prop = PropFile::new(&prop_path);
+        let mtime = fs::metadata(&prop_path).map(|m| m.mtime()).unwrap_or(0);
+        let fingerprint = format!(
+            "{}|{}|{}",
+            prop.get("version").unwrap_or_default(),
+            prop.get("versionCode").unwrap_or_default(),
+            mtime
+        );
+
+        modules.insert(id, fingerprint);
+    }
+
+    modules
+}
+
+fn set_disabled(id: &str, disabled: bool) {
+    let path = Path::new(defs::MODULES_DIR)
+        .join(id)
+        .join(defs::DISABLE_FILE_NAME);
+
+    let result = if disabled {
+        fs::File::create(&path).map(|_| ())
+    } else if path.exists() {
+        fs::remove_file(&path)
+    } else {
+        Ok(())
+    };
+
+    if let Err(e) = result {
+        log::warn!("Rescue: failed to toggle {}: {}", path.display(), e);
+    }
+}
+
+fn journal(step: u32, event: RescueEvent, modules: Vec<String>, message: &str) {
+    let mut entries: Vec<JournalEntry> = load_json(JOURNAL_FILE).unwrap_or_default();
+
+    entries.push(JournalEntry {
+        timestamp: now(),
+        step,
+        event,
+        modules,
+        message: message.to_string(),
+    });
+
+    if entries.len() > JOURNAL_MAX_ENTRIES {
+        entries.drain(..entries.len() - JOURNAL_MAX_ENTRIES);
+    }
+
+    if let Err(e) = save_json(JOURNAL_FILE, &entries) {
+        log::warn!("Failed to write rescue journal: {}", e);
+    }
+}
+
+fn load_json<T: DeserializeOwned>(path: &str) -> Option<T> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save_json<T: Serialize>(path: &str, value: &T) -> Result<()> {
+    fs::create_dir_all(RESCUE_DIR)?;
+    utils::atomic_write(path, serde_json::to_string_pretty(value)?)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn session(suspects: &[&str], disabled: &[&str]) -> RescueSession {
+        RescueSession {
+            suspects: ids(suspects),
+            disabled: ids(disabled),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plan_disables_the_larger_half() {
+        assert_eq!(plan(&[]), Step::Inconclusive);
+        assert_eq!(plan(&ids(&["a"])), Step::Verdict("a".into()));
+        assert_eq!(
+            plan(&ids(&["a", "b", "c", "d", "e"])),
+            Step::Disable(["a", "b", "c"].map(String::from).into())
+        );
+        assert_eq!(
+            plan(&ids(&["a", "b"])),
+            Step::Disable(["a"].map(String::from).into())
+        );
+    }
+
+    #[test]
+    fn failed_boot_keeps_enabled_suspects() {
+        let mut session = session(&["a", "b", "c", "d"], &["a", "b"]);
+        narrow(&mut session, false);
+
+        assert_eq!(session.suspects, ids(&["c", "d"]));
+        assert_eq!(session.cleared, ids(&["a", "b"]));
+    }
+
+    #[test]
+    fn successful_boot_keeps_disabled_suspects() {
+        let mut session = session(&["a", "b", "c", "d"], &["a", "b"]);
+        session.cleared = ids(&["z"]);
+        narrow(&mut session, true);
+
+        assert_eq!(session.suspects, ids(&["a", "b"]));
+        assert_eq!(session.cleared, ids(&["z", "c", "d"]));
+    }
+
+    #[test]
+    fn bisection_finds_every_culprit() {
+        let modules = ids(&["a", "b", "c", "d", "e", "f", "g"]);
+
+        for culprit in &modules {
+            let mut session = RescueSession {
+                suspects: modules.clone(),
+                ..Default::default()
+            };
+            let mut boots = 0;
+
+            let verdict = loop {
+                match plan(&session.suspects) {
+                    Step::Verdict(id) => break id,
+                    Step::Inconclusive => panic!("bisection lost {culprit}"),
+                    Step::Disable(next) => {
+                        // 元凶被禁用时测试启动才会成功
+                        let booted = next.contains(culprit);
+                        session.disabled = next.into_iter().collect();
+                        narrow(&mut session, booted);
+                        boots += 1;
+                    }
+                }
+            };
+
+            assert_eq!(&verdict, culprit);
+            assert!(boots <= 3, "{culprit} took {boots} boots");
+            assert_eq!(session.cleared.len(), modules.len() - 1);
+        }
+    }
+
+    #[test]
+    fn contradictory_results_are_inconclusive() {
+        // 嫌疑模块在测试期间被移出禁用集合（例如用户手动启用），两次结果互相矛盾
+        let mut session = session(&["a", "b"], &["a"]);
+        narrow(&mut session, true);
+        assert_eq!(session.suspects, ids(&["a"]));
+
+        session.disabled.clear();
+        narrow(&mut session, true);
+        assert_eq!(plan(&session.suspects), Step::Inconclusive);
+    }
+}
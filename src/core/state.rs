@@ -8,11 +8,16 @@ use std::{
 };
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::defs;
+use crate::{
+    core::{planner::DiagnosticIssue, sync::SyncStats},
+    defs,
+};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// State written after each mount run
+#[derive(Debug, Serialize, Deserialize, Default, JsonSchema)]
 pub struct RuntimeState {
     pub timestamp: u64,
     pub pid: u32,
@@ -30,6 +35,15 @@ pub struct RuntimeState {
     pub storage_percent: u8,
     #[serde(default)]
     pub zygisksu_enforce: bool,
+    #[serde(default)]
+    pub rolled_back_mounts: Vec<String>,
+    #[serde(default)]
+    pub rollback_reason: Option<String>,
+    #[serde(default)]
+    pub diagnostics: Vec<DiagnosticIssue>,
+    /// Work done by the module sync of this run
+    #[serde(default)]
+    pub sync: SyncStats,
 }
 
 impl RuntimeState {
@@ -65,6 +79,10 @@ impl RuntimeState {
             storage_used: storage_info.1,
             storage_percent: storage_info.2,
             zygisksu_enforce,
+            rolled_back_mounts: Vec::new(),
+            rollback_reason: None,
+            diagnostics: Vec::new(),
+            sync: SyncStats::default(),
         }
     }
 
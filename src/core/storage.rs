@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
+    collections::HashSet,
     fs,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
     process::Command,
 };
@@ -11,16 +13,150 @@ use anyhow::{Context, Result, bail, ensure};
 use jwalk::WalkDir;
 use rustix::{
     fs::Mode,
-    mount::{MountPropagationFlags, UnmountFlags, mount_change, unmount as umount},
+    mount::{MountPropagationFlags, UnmountFlags, mount_change, mount_move, unmount as umount},
 };
 use serde::Serialize;
-use crate::conf::config::OverlayMode;
 
-#[cfg(any(target_os = "linux", target_os = "android"))]
-use crate::try_umount::send_umountable;
-use crate::{core::state::RuntimeState, mount::overlayfs::utils as overlay_utils, utils};
+use crate::{conf::config::OverlayMode, defs};
+
+use crate::{
+    core::{
+        planner::{DiagnosticIssue, DiagnosticLevel},
+        state::RuntimeState,
+        sync::SyncStats,
+    },
+    utils,
+};
 
 const DEFAULT_SELINUX_CONTEXT: &str = "u:object_r:system_file:s0";
+const EROFS_IMAGE_NAME: &str = "mhm_modules.erofs";
+const EROFS_TMP_MOUNT: &str = "mhm_erofs_tmp";
+const EROFS_STAGING_MOUNT: &str = "mhm_erofs_staging";
+
+const BLOCK_SIZE: u64 = 4096;
+const MIB: u64 = 1024 * 1024;
+/// 估算之外预留的比例与下限，容纳文件系统元数据、同步清单与 Magic Mount 工作区
+const HEADROOM_PERCENT: u64 = 20;
+const MIN_HEADROOM_BYTES: u64 = 32 * MIB;
+const MIN_HEADROOM_INODES: u64 = 1024;
+/// tmpfs 最多占用当前可用内存的比例
+const TMPFS_RAM_PERCENT: u64 = 50;
+/// tmpfs 中每个 inode 常驻内存的估算开销（inode 与 dentry）
+const TMPFS_INODE_BYTES: u64 = 1024;
+/// EXT4 镜像中每个 inode 占用的 inode 表空间（mkfs.ext4 默认 inode 大小）
+const EXT4_INODE_BYTES: u64 = 256;
+
+/// 同步前估算的工作区占用
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Requirement {
+    pub bytes: u64,
+    pub inodes: u64,
+}
+
+impl Requirement {
+    pub fn merge(&mut self, other: &Requirement) {
+        self.bytes += other.bytes;
+        self.inodes += other.inodes;
+    }
+
+    /// 加上余量，用作 tmpfs 的上限与 EXT4 镜像的大小
+    fn with_headroom(self) -> Self {
+        Self {
+            bytes: self.bytes + (self.bytes * HEADROOM_PERCENT / 100).max(MIN_HEADROOM_BYTES),
+            inodes: self.inodes + (self.inodes * HEADROOM_PERCENT / 100).max(MIN_HEADROOM_INODES),
+        }
+    }
+}
+
+/// 预检时可用的资源，None 表示无法获取、不作限制
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Available {
+    /// tmpfs 可使用的内存
+    pub ram: Option<u64>,
+    /// 镜像所在文件系统的剩余空间
+    pub disk: Option<u64>,
+    /// 镜像所在文件系统的剩余 inode
+    pub disk_inodes: Option<u64>,
+}
+
+/// 某个后端分别需要的内存、磁盘空间与磁盘 inode
+#[derive(Debug, PartialEq)]
+struct Cost {
+    ram: u64,
+    disk: u64,
+    disk_inodes: u64,
+}
+
+impl Cost {
+    fn of(mode: &OverlayMode, capacity: Requirement) -> Self {
+        let staging = capacity.bytes + capacity.inodes * TMPFS_INODE_BYTES;
+        match mode {
+            OverlayMode::Tmpfs => Self {
+                ram: staging,
+                disk: 0,
+                disk_inodes: 0,
+            },
+            OverlayMode::Ext4 => Self {
+                ram: 0,
+                disk: ext4_image_bytes(capacity),
+                disk_inodes: 1,
+            },
+            // 先暂存在 tmpfs 中，打包时镜像写到磁盘，内存与磁盘分别检查
+            OverlayMode::Erofs => Self {
+                ram: staging,
+                disk: capacity.bytes,
+                disk_inodes: 1,
+            },
+        }
+    }
+
+    /// 放不下时返回缺少哪些资源
+    fn shortfall(&self, available: &Available) -> Option<String> {
+        let mut missing = Vec::new();
+
+        if let Some(ram) = available.ram
+            && self.ram > ram
+        {
+            missing.push(format!(
+                "{} of RAM needed, {} available",
+                describe_mib(Some(self.ram)),
+                describe_mib(Some(ram))
+            ));
+        }
+        if let Some(disk) = available.disk
+            && self.disk > disk
+        {
+            missing.push(format!(
+                "{} of disk needed, {} free",
+                describe_mib(Some(self.disk)),
+                describe_mib(Some(disk))
+            ));
+        }
+        if let Some(inodes) = available.disk_inodes
+            && self.disk_inodes > inodes
+        {
+            missing.push(format!(
+                "{} free inodes on disk needed, {} left",
+                self.disk_inodes, inodes
+            ));
+        }
+
+        (!missing.is_empty()).then(|| missing.join(", "))
+    }
+}
+
+/// EXT4 镜像大小：数据加上 inode 表
+fn ext4_image_bytes(capacity: Requirement) -> u64 {
+    capacity.bytes + capacity.inodes * EXT4_INODE_BYTES
+}
+
+/// 存储预检的结果：实际使用的后端、含余量的容量以及回退产生的诊断
+#[derive(Debug)]
+pub struct Preflight {
+    pub mode: OverlayMode,
+    pub capacity: Requirement,
+    pub diagnostics: Vec<DiagnosticIssue>,
+}
 
 pub struct StorageHandle {
     pub mount_point: PathBuf,
@@ -29,16 +165,116 @@ pub struct StorageHandle {
 
 impl StorageHandle {
     pub fn commit(&mut self, _disable_umount: bool) -> Result<()> {
-        // 在瞬时模式下，同步由 perform_sync 实时完成
-        if self.mode == "erofs_staging" {
-            log::info!("EROFS staging commit triggered in transient mode.");
+        // 只有 EROFS 暂存区需要提交：打包为只读镜像并替换 tmpfs
+        if self.mode != "erofs_staging" {
+            return Ok(());
         }
+
+        let swapped = self
+            .pack_erofs()
+            .and_then(|erofs_tmp| self.swap_in_erofs(&erofs_tmp));
+
+        match swapped {
+            Ok(true) => {
+                self.mode = "erofs".to_string();
+                log::info!(">> EROFS storage committed at {}", self.mount_point.display());
+            }
+            Ok(false) => {
+                log::warn!("EROFS swap failed. Falling back to tmpfs staging as storage.");
+                self.mode = "tmpfs".to_string();
+            }
+            Err(e) if utils::is_mounted(&self.mount_point) => {
+                log::warn!(
+                    "EROFS commit failed: {:#}. Falling back to tmpfs staging as storage.",
+                    e
+                );
+                self.mode = "tmpfs".to_string();
+            }
+            Err(e) => return Err(e.context("EROFS commit lost the staging storage")),
+        }
+
         Ok(())
     }
+
+    /// 打包暂存区并挂载到临时位置验证，返回该临时挂载点；失败时暂存区保持不变
+    fn pack_erofs(&self) -> Result<PathBuf> {
+        let base = self
+            .mount_point
+            .parent()
+            .context("Invalid storage mount point")?;
+        let image_path = base.join(EROFS_IMAGE_NAME);
+        let erofs_tmp = base.join(EROFS_TMP_MOUNT);
+
+        // 镜像只读，必须在打包前预留 Magic Mount 工作区的挂载点
+        utils::ensure_dir_exists(
+            self.mount_point
+                .join(defs::MAGIC_WORKSPACE_DIR)
+                .join("workdir"),
+        )?;
+
+        if image_path.exists() {
+            let _ = fs::remove_file(&image_path);
+        }
+
+        utils::create_erofs_image(&self.mount_point, &image_path)?;
+
+        // 先挂载到临时位置验证镜像，避免在移走暂存区后才发现失败
+        let mounted = utils::mount_erofs_image(&image_path, &erofs_tmp);
+        // 与瞬时 EXT4 一致：删除镜像文件，由 loop 设备保持引用
+        let _ = fs::remove_file(&image_path);
+        if let Err(e) = mounted {
+            let _ = fs::remove_dir(&erofs_tmp);
+            return Err(e);
+        }
+
+        Ok(erofs_tmp)
+    }
+
+    /// 用已验证的 EROFS 挂载替换暂存 tmpfs
+    /// 暂存区先移到旁边，替换失败时移回原处并返回 false；只有暂存区无法复原时才返回错误
+    fn swap_in_erofs(&self, erofs_tmp: &Path) -> Result<bool> {
+        let staging_tmp = erofs_tmp.with_file_name(EROFS_STAGING_MOUNT);
+        let discard = |path: &Path| {
+            let _ = umount(path, UnmountFlags::DETACH);
+            let _ = fs::remove_dir(path);
+        };
+
+        utils::ensure_dir_exists(&staging_tmp)?;
+        if let Err(e) = mount_move(&self.mount_point, &staging_tmp) {
+            log::warn!("Failed to move EROFS staging tmpfs aside: {}", e);
+            discard(erofs_tmp);
+            let _ = fs::remove_dir(&staging_tmp);
+            return Ok(false);
+        }
+
+        if let Err(e) = mount_move(erofs_tmp, &self.mount_point) {
+            log::warn!(
+                "Failed to move EROFS mount {} -> {}: {}",
+                erofs_tmp.display(),
+                self.mount_point.display(),
+                e
+            );
+            discard(erofs_tmp);
+            mount_move(&staging_tmp, &self.mount_point).with_context(|| {
+                format!(
+                    "Failed to restore EROFS staging tmpfs {} -> {}",
+                    staging_tmp.display(),
+                    self.mount_point.display()
+                )
+            })?;
+            let _ = fs::remove_dir(&staging_tmp);
+            return Ok(false);
+        }
+
+        discard(&staging_tmp);
+        let _ = fs::remove_dir(erofs_tmp);
+
+        Ok(true)
+    }
 }
 
 #[derive(Serialize)]
-struct StorageStatus {
+pub struct StorageStatus {
     #[serde(rename = "type")]
     mode: String,
     mount_point: String,
@@ -46,6 +282,70 @@ struct StorageStatus {
     total_size: u64,
     used_size: u64,
     supported_modes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_sync: Option<SyncStats>,
+    /// 工作区中每个模块副本的占用，从大到小
+    modules: Vec<ModuleFootprint>,
+    /// 因硬链接或去重而未重复占用的字节数
+    dedup_saved_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct ModuleFootprint {
+    id: String,
+    files: u64,
+    bytes: u64,
+}
+
+/// 统计工作区中每个模块副本的文件数与字节数，模块内的硬链接只计一次
+/// 同时返回与其他副本共享 inode 而节省的字节数
+fn module_footprints(mnt_base: &Path) -> (Vec<ModuleFootprint>, u64) {
+    let Ok(entries) = fs::read_dir(mnt_base) else {
+        return (Vec::new(), 0);
+    };
+
+    let mut shared = HashSet::new();
+    let mut saved = 0;
+
+    let mut footprints: Vec<ModuleFootprint> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter(|e| {
+            let name = e.file_name();
+            name != defs::MAGIC_WORKSPACE_DIR && name != "lost+found"
+        })
+        .map(|e| {
+            let mut footprint = ModuleFootprint {
+                id: e.file_name().to_string_lossy().to_string(),
+                files: 0,
+                bytes: 0,
+            };
+            let mut seen = HashSet::new();
+
+            for entry in WalkDir::new(e.path()).skip_hidden(false).into_iter().flatten() {
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                let inode = (metadata.dev(), metadata.ino());
+                if metadata.nlink() > 1 && !shared.insert(inode) {
+                    saved += metadata.len();
+                }
+                if metadata.nlink() > 1 && !seen.insert(inode) {
+                    continue;
+                }
+                footprint.files += 1;
+                footprint.bytes += metadata.len();
+            }
+
+            footprint
+        })
+        .collect();
+
+    footprints.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.id.cmp(&b.id)));
+    (footprints, saved)
 }
 
 pub fn get_usage(path: &Path) -> (u64, u64, u8) {
@@ -64,42 +364,161 @@ pub fn get_usage(path: &Path) -> (u64, u64, u8) {
     }
 }
 
-fn calculate_total_size(path: &Path) -> Result<u64> {
-    let mut total_size = 0;
-    if path.is_dir() {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            if file_type.is_file() {
-                total_size += entry.metadata()?.len();
-            } else if file_type.is_dir() {
-                total_size += calculate_total_size(&entry.path())?;
-            }
+/// 统计 root 下被 include 接受的条目复制后的占用，文件按块向上取整，硬链接只计一次
+/// include 返回 false 的目录连同其子树一起跳过
+pub fn measure<F>(root: &Path, include: F) -> Result<Requirement>
+where
+    F: Fn(&str, fs::FileType) -> bool,
+{
+    let mut requirement = Requirement {
+        bytes: BLOCK_SIZE,
+        inodes: 1,
+    };
+    let mut seen = HashSet::new();
+
+    let walker = walkdir::WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| {
+            e.path()
+                .strip_prefix(root)
+                .is_ok_and(|r| include(&r.to_string_lossy(), e.file_type()))
+        });
+
+    for entry in walker {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file()
+            && metadata.nlink() > 1
+            && !seen.insert((metadata.dev(), metadata.ino()))
+        {
+            continue;
         }
+
+        requirement.inodes += 1;
+        requirement.bytes += if metadata.is_file() {
+            metadata.len().next_multiple_of(BLOCK_SIZE)
+        } else {
+            BLOCK_SIZE
+        };
     }
-    Ok(total_size)
+
+    Ok(requirement)
 }
 
-fn check_image<P>(img: P) -> Result<()>
-where
-    P: AsRef<Path>,
-{
-    let path = img.as_ref();
-    let path_str = path.to_str().context("Invalid path string")?;
-    let result = Command::new("e2fsck")
-        .args(["-yf", path_str])
-        .status()
-        .with_context(|| format!("Failed to exec e2fsck {}", path.display()))?;
-    let code = result.code();
-
-    log::info!("e2fsck exit code: {}", code.unwrap_or(-1));
-    Ok(())
+/// 读取 /proc/meminfo 中的 MemAvailable
+fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(kib * 1024)
+}
+
+fn available_disk(path: &Path) -> (Option<u64>, Option<u64>) {
+    match rustix::fs::statvfs(path) {
+        Ok(stat) => (Some(stat.f_bavail * stat.f_frsize), Some(stat.f_favail)),
+        Err(_) => (None, None),
+    }
 }
 
+fn describe_mib(bytes: Option<u64>) -> String {
+    bytes.map_or_else(|| "unknown".to_string(), |b| format!("{} MiB", b / MIB))
+}
+
+/// 根据估算的需求与当前可用的内存、磁盘确定存储后端
+/// 首选后端放不下时依次尝试 EXT4 与 tmpfs，都放不下则直接拒绝，而不是同步到一半才失败
+pub fn preflight(
+    mnt_base: &Path,
+    mode: &OverlayMode,
+    requirement: Requirement,
+) -> Result<Preflight> {
+    let capacity = requirement.with_headroom();
+    // 瞬时 EXT4 镜像与 EROFS 镜像都写在挂载点的上级目录
+    let (disk, disk_inodes) = mnt_base.parent().map_or((None, None), available_disk);
+    let available = Available {
+        ram: available_memory().map(|m| m * TMPFS_RAM_PERCENT / 100),
+        disk,
+        disk_inodes,
+    };
+
+    log::info!(
+        ">> Storage preflight: {} and {} inodes required, tmpfs budget {}, disk free {} ({} inodes)",
+        describe_mib(Some(capacity.bytes)),
+        capacity.inodes,
+        describe_mib(available.ram),
+        describe_mib(available.disk),
+        available
+            .disk_inodes
+            .map_or_else(|| "unknown".to_string(), |i| i.to_string())
+    );
+
+    let preferred = match mode {
+        OverlayMode::Erofs if !utils::is_erofs_supported() => {
+            log::warn!("EROFS is not listed in /proc/filesystems. Falling back to tmpfs.");
+            OverlayMode::Tmpfs
+        }
+        other => other.clone(),
+    };
+
+    choose(preferred, capacity, &available)
+}
+
+/// 按首选、EXT4、tmpfs 的顺序选出第一个放得下的后端
+fn choose(
+    preferred: OverlayMode,
+    capacity: Requirement,
+    available: &Available,
+) -> Result<Preflight> {
+    let mut rejected: Vec<(OverlayMode, String)> = Vec::new();
+
+    for mode in [preferred.clone(), OverlayMode::Ext4, OverlayMode::Tmpfs] {
+        if rejected.iter().any(|(m, _)| *m == mode) {
+            continue;
+        }
+
+        let Some(reason) = Cost::of(&mode, capacity).shortfall(available) else {
+            let mut diagnostics = Vec::new();
+            if let Some((_, reason)) = rejected.first() {
+                let message = format!(
+                    "{:?} storage cannot hold the modules ({}), using {:?} instead",
+                    preferred, reason, mode
+                );
+                log::warn!("{}", message);
+                diagnostics.push(DiagnosticIssue {
+                    level: DiagnosticLevel::Warning,
+                    context: "storage".to_string(),
+                    message,
+                });
+            }
+
+            return Ok(Preflight {
+                mode,
+                capacity,
+                diagnostics,
+            });
+        };
+
+        rejected.push((mode, reason));
+    }
+
+    let reasons: Vec<String> = rejected
+        .iter()
+        .map(|(mode, reason)| format!("{:?}: {}", mode, reason))
+        .collect();
+    bail!(
+        "Not enough space for the modules ({} and {} inodes required): {}",
+        describe_mib(Some(capacity.bytes)),
+        capacity.inodes,
+        reasons.join("; ")
+    );
+}
+
+/// 按预检给出的容量建立存储，mode 应来自 preflight
 pub fn setup(
     mnt_base: &Path,
     mode: &OverlayMode,
     mount_source: &str,
+    capacity: Requirement,
 ) -> Result<StorageHandle> {
     log::info!(">> Setting up transient storage: [{:?}]", mode);
 
@@ -108,31 +527,49 @@ pub fn setup(
     }
     utils::ensure_dir_exists(mnt_base)?;
 
+    let mode_name = match mode {
+        OverlayMode::Erofs => "erofs_staging".to_string(),
+        _ => format!("{:?}", mode).to_lowercase(),
+    };
+
     match mode {
         OverlayMode::Tmpfs => {
-            utils::mount_tmpfs(mnt_base, mount_source)?;
+            utils::mount_tmpfs_sized(mnt_base, mount_source, capacity.bytes, capacity.inodes)?;
         }
         OverlayMode::Ext4 => {
             // 瞬时 EXT4 逻辑：创建 -> 挂载 -> 立即删除镜像文件
             let img_path = mnt_base.parent().context("Invalid base")?.join("mhm_temp.img");
             
             Command::new("dd")
-                .args(["if=/dev/zero", &format!("of={}", img_path.display()), "bs=1M", "count=0", "seek=1024"])
+                .args([
+                    "if=/dev/zero",
+                    &format!("of={}", img_path.display()),
+                    "bs=1M",
+                    "count=0",
+                    &format!("seek={}", ext4_image_bytes(capacity).div_ceil(MIB)),
+                ])
                 .status()?;
             
-            Command::new("mkfs.ext4").args(["-b", "4096", "-F", &img_path.to_string_lossy()]).status()?;
+            let status = Command::new("mkfs.ext4")
+                .args(["-b", "4096", "-N", &capacity.inodes.to_string(), "-F", &img_path.to_string_lossy()])
+                .status()?;
+            if !status.success() {
+                let _ = fs::remove_file(&img_path);
+                bail!("Failed to format transient ext4 image");
+            }
             
-            Command::new("mount")
+            let status = Command::new("mount")
                 .args(["-t", "ext4", "-o", "loop,rw", &img_path.to_string_lossy()])
                 .arg(mnt_base)
                 .status()?;
 
             // 关键：删除文件，内核保留 loop 挂载句柄
             let _ = fs::remove_file(&img_path);
+            ensure!(status.success(), "Failed to mount transient ext4 image");
         }
         OverlayMode::Erofs => {
-            // 简化处理，目前 fallback 到 tmpfs
-            utils::mount_tmpfs(mnt_base, mount_source)?;
+            // 先同步到 tmpfs 暂存区，commit 时再打包为 EROFS 镜像
+            utils::mount_tmpfs_sized(mnt_base, mount_source, capacity.bytes, capacity.inodes)?;
         }
     }
 
@@ -142,89 +579,7 @@ pub fn setup(
 
     Ok(StorageHandle {
         mount_point: mnt_base.to_path_buf(),
-        mode: format!("{:?}", mode).to_lowercase(), 
-    })
-}
-fn try_setup_tmpfs(target: &Path, mount_source: &str) -> Result<bool> {
-    if utils::mount_tmpfs(target, mount_source).is_ok() {
-        if utils::is_overlay_xattr_supported(target) {
-            log::info!("Tmpfs mounted and supports xattrs (CONFIG_TMPFS_XATTR=y).");
-            return Ok(true);
-        } else {
-            log::warn!("Tmpfs mounted but XATTRs (trusted.*) are NOT supported.");
-            log::warn!(">> Your kernel likely lacks CONFIG_TMPFS_XATTR=y.");
-            log::warn!(">> Falling back to legacy Ext4 image mode.");
-            let _ = umount(target, UnmountFlags::DETACH);
-        }
-    }
-
-    Ok(false)
-}
-
-fn setup_ext4_image(target: &Path, img_path: &Path, moduledir: &Path) -> Result<StorageHandle> {
-    if !img_path.exists() || check_image(img_path).is_err() {
-        log::info!("Modules image missing or corrupted. Fallback to creation.");
-
-        if img_path.exists()
-            && let Err(e) = fs::remove_file(img_path)
-        {
-            log::warn!("Failed to remove old image: {}", e);
-        }
-
-        log::info!("- Preparing image");
-
-        let total_size = calculate_total_size(moduledir)?;
-        log::info!(
-            "Total size of files in '{}': {} bytes",
-            moduledir.display(),
-            total_size,
-        );
-
-        let grow_size = 128 * 1024 * 1024 + total_size;
-
-        fs::File::create(img_path)
-            .context("Failed to create ext4 image file")?
-            .set_len(grow_size)
-            .context("Failed to extend ext4 image")?;
-
-        let result = Command::new("mkfs.ext4")
-            .arg("-b")
-            .arg("1024")
-            .arg(img_path)
-            .stdout(std::process::Stdio::piped())
-            .output()?;
-
-        ensure!(
-            result.status.success(),
-            "Failed to format ext4 image: {}",
-            String::from_utf8(result.stderr)?
-        );
-
-        log::info!("Checking Image");
-        check_image(img_path)?;
-    }
-
-    utils::lsetfilecon(img_path, "u:object_r:ksu_file:s0").ok();
-
-    log::info!("- Mounting image");
-
-    utils::ensure_dir_exists(target)?;
-    if overlay_utils::AutoMountExt4::try_new(img_path, target, false).is_err() {
-        if utils::repair_image(img_path).is_ok() {
-            overlay_utils::AutoMountExt4::try_new(img_path, target, false)
-                .context("Failed to mount modules.img after repair")
-                .map(|_| ())?;
-        } else {
-            bail!("Failed to repair modules.img");
-        }
-    }
-
-    log::info!("mounted {} to {}", img_path.display(), target.display());
-
-Ok(StorageHandle {
-        mount_point: target.to_path_buf(),
-        mode: "ext4".to_string(),
-        // 移除已不存在的 backing_image 字段
+        mode: mode_name,
     })
 }
 
@@ -247,11 +602,12 @@ pub fn finalize_storage_permissions(target: &Path) {
     }
 }
 
-pub fn print_status() -> Result<()> {
+pub fn status() -> StorageStatus {
     let state = RuntimeState::load().ok();
     let fallback_mnt = crate::conf::config::Config::load_default()
         .map(|c| c.hybrid_mnt_dir)
         .unwrap_or_else(|_| crate::defs::DEFAULT_HYBRID_MNT_DIR.to_string());
+    let last_sync = state.as_ref().map(|s| s.sync.clone());
     let (mnt_base, expected_mode) = if let Some(ref s) = state {
         (s.mount_point.clone(), s.storage_mode.clone())
     } else {
@@ -266,9 +622,14 @@ pub fn print_status() -> Result<()> {
 
     let mut percent = 0;
 
+    let mut modules = Vec::new();
+    let mut dedup_saved_bytes = 0;
+
     if utils::is_mounted(&mnt_base)
         && let Ok(stat) = rustix::fs::statvfs(&mnt_base)
     {
+        (modules, dedup_saved_bytes) = module_footprints(&mnt_base);
+
         mode = if expected_mode != "unknown" {
             expected_mode
         } else {
@@ -284,7 +645,10 @@ pub fn print_status() -> Result<()> {
         percent = (used * 100).checked_div(total).unwrap_or(0) as u8;
     }
 
-    let mut supported_modes = vec!["ext4".to_string(), "erofs".to_string()];
+    let mut supported_modes = vec!["ext4".to_string()];
+    if utils::is_erofs_supported() {
+        supported_modes.push("erofs".to_string());
+    }
     let check_dir = Path::new("/data/local/tmp/.mh_xattr_chk");
     if utils::mount_tmpfs(check_dir, "mh_check").is_ok() {
         if utils::is_overlay_xattr_supported(check_dir) {
@@ -294,16 +658,149 @@ pub fn print_status() -> Result<()> {
         let _ = fs::remove_dir(check_dir);
     }
 
-    let status = StorageStatus {
+    StorageStatus {
         mode,
         mount_point: mnt_base.to_string_lossy().to_string(),
         usage_percent: percent,
         total_size: total,
         used_size: used,
         supported_modes,
-    };
+        last_sync,
+        modules,
+        dedup_saved_bytes,
+    }
+}
 
-    println!("{}", serde_json::to_string(&status)?);
+pub fn print_status() -> Result<()> {
+    println!("{}", serde_json::to_string(&status())?);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * MIB;
+
+    fn capacity(mib: u64, inodes: u64) -> Requirement {
+        Requirement {
+            bytes: mib * MIB,
+            inodes,
+        }
+    }
+
+    fn available(ram_mib: u64, disk_mib: u64, disk_inodes: u64) -> Available {
+        Available {
+            ram: Some(ram_mib * MIB),
+            disk: Some(disk_mib * MIB),
+            disk_inodes: Some(disk_inodes),
+        }
+    }
+
+    #[test]
+    fn preferred_backend_is_kept_when_it_fits() {
+        let result = choose(
+            OverlayMode::Erofs,
+            capacity(100, 1000),
+            &available(1024, 1024, 1000),
+        )
+        .unwrap();
+
+        assert_eq!(result.mode, OverlayMode::Erofs);
+        assert!(result.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn unknown_resources_do_not_limit() {
+        let result = choose(
+            OverlayMode::Tmpfs,
+            capacity(100 * 1024, 1),
+            &Available::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result.mode, OverlayMode::Tmpfs);
+    }
+
+    #[test]
+    fn erofs_checks_ram_and_disk_separately() {
+        // 内存足够、磁盘不足：EROFS 与 EXT4 都写不下镜像，只能用 tmpfs
+        let result = choose(
+            OverlayMode::Erofs,
+            capacity(200, 100),
+            &available(1024, 100, 1000),
+        )
+        .unwrap();
+        assert_eq!(result.mode, OverlayMode::Tmpfs);
+        assert!(result.diagnostics[0].message.contains("of disk needed"));
+
+        // 磁盘足够、内存不足：暂存区放不下，改用 EXT4
+        let result = choose(
+            OverlayMode::Erofs,
+            capacity(200, 100),
+            &available(100, 1024, 1000),
+        )
+        .unwrap();
+        assert_eq!(result.mode, OverlayMode::Ext4);
+        assert!(result.diagnostics[0].message.contains("of RAM needed"));
+    }
+
+    #[test]
+    fn inode_heavy_modules_leave_tmpfs() {
+        // 数据只有 10 MiB，但 20 万个 inode 在 tmpfs 中约占 195 MiB 内存
+        let result = choose(
+            OverlayMode::Tmpfs,
+            capacity(10, 200_000),
+            &available(128, GIB / MIB, 1000),
+        )
+        .unwrap();
+
+        assert_eq!(result.mode, OverlayMode::Ext4);
+        assert_eq!(result.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn ext4_image_accounts_for_the_inode_table() {
+        assert_eq!(ext4_image_bytes(capacity(10, 4096)), 10 * MIB + 4096 * 256);
+        assert_eq!(
+            Cost::of(&OverlayMode::Ext4, capacity(10, 4096)).disk,
+            11 * MIB
+        );
+    }
+
+    #[test]
+    fn disk_without_free_inodes_rejects_images() {
+        let result = choose(
+            OverlayMode::Ext4,
+            capacity(100, 100),
+            &available(1024, 1024, 0),
+        )
+        .unwrap();
+
+        assert_eq!(result.mode, OverlayMode::Tmpfs);
+        assert!(
+            result.diagnostics[0]
+                .message
+                .contains("free inodes on disk")
+        );
+    }
+
+    #[test]
+    fn rejects_when_nothing_fits() {
+        let err = choose(
+            OverlayMode::Ext4,
+            capacity(500, 100),
+            &available(100, 100, 1000),
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(
+            err.starts_with("Not enough space for the modules (500 MiB and 100 inodes required)")
+        );
+        assert!(err.contains("Ext4: "));
+        assert!(err.contains("Tmpfs: "));
+        assert!(!err.contains("Erofs"));
+    }
+}
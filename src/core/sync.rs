@@ -1,90 +1,468 @@
 // Copyright 2025 Meta-Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
 use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
-use crate::{core::inventory::Module, defs, utils};
+use crate::{
+    conf::config::Config,
+    core::{
+        dedup,
+        inventory::{Module, MountMode},
+        manifest::{self, Entry, EntryKind, Manifest},
+        planner::{DiagnosticIssue, DiagnosticLevel},
+        storage::{self, Requirement},
+    },
+    defs,
+    utils::{self, SyncOptions, XattrFilter},
+};
 
-/// 执行智能模块同步
-/// force: 是否强制同步（忽略 module.prop 对比）
-pub fn perform_sync(modules: &[Module], target_base: &Path, force: bool) -> Result<()> {
+const MODULE_PROP_FILE_NAME: &str = "module.prop";
+const SELINUX_XATTR: &str = "security.selinux";
+
+/// 同步统计，写入运行状态并由 storage 命令展示
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SyncStats {
+    /// Modules whose copy changed during the sync
+    pub modules_synced: usize,
+    /// Files and links written to the workspace
+    pub files_copied: usize,
+    /// Bytes of file content written to the workspace
+    pub bytes_copied: u64,
+    /// Entries deleted because they were removed or replaced in the source
+    pub files_removed: usize,
+    /// Entries left untouched because the manifest matched
+    pub files_unchanged: usize,
+    /// Files sharing storage with an identical file after deduplication
+    #[serde(default)]
+    pub files_deduplicated: usize,
+    /// Bytes saved by deduplication
+    #[serde(default)]
+    pub bytes_deduplicated: u64,
+}
+
+impl SyncStats {
+    fn merge(&mut self, other: &SyncStats) {
+        self.modules_synced += other.modules_synced;
+        self.files_copied += other.files_copied;
+        self.bytes_copied += other.bytes_copied;
+        self.files_removed += other.files_removed;
+        self.files_unchanged += other.files_unchanged;
+        self.files_deduplicated += other.files_deduplicated;
+        self.bytes_deduplicated += other.bytes_deduplicated;
+    }
+}
+
+/// 估算同步全部模块的可挂载内容所需的工作区容量，不扣除跨模块去重节省的部分
+pub fn estimate(modules: &[Module], config: &Config) -> Requirement {
+    let results: Vec<Requirement> = modules
+        .par_iter()
+        .filter_map(|module| {
+            storage::measure(&module.source_path, |relative, ft| {
+                is_mountable(module, &config.partitions, relative, ft.is_dir())
+            })
+            .inspect_err(|e| log::warn!("Failed to measure module {}: {:#}", module.id, e))
+            .ok()
+        })
+        .collect();
+
+    let mut total = Requirement::default();
+    for requirement in &results {
+        total.merge(requirement);
+    }
+
+    total
+}
+
+/// 执行增量模块同步：按清单对比源目录与已同步副本，只复制、替换或删除变化的条目
+/// force: 是否忽略已有清单进行全量同步
+pub fn perform_sync(
+    modules: &[Module],
+    target_base: &Path,
+    config: &Config,
+    force: bool,
+) -> Result<SyncStats> {
     if force {
-        log::info!(">> Mode change detected or force requested: performing FULL sync to {}", target_base.display());
+        log::info!(">> Force requested: performing FULL sync to {}", target_base.display());
     } else {
-        log::info!("Starting smart module sync to {}", target_base.display());
+        log::info!("Starting incremental module sync to {}", target_base.display());
     }
 
     prune_orphaned_modules(modules, target_base)?;
 
-    modules.par_iter().for_each(|module| {
-        let dst = target_base.join(&module.id);
+    let opts = sync_options(config);
+
+    let results: Vec<SyncStats> = modules
+        .par_iter()
+        .filter_map(|module| {
+            let dst = target_base.join(&module.id);
+
+            match sync_module(module, &dst, &config.partitions, &opts, force) {
+                Ok(None) => {
+                    log::debug!("Skipping module without mountable content: {}", module.id);
+                    if dst.exists() {
+                        let _ = fs::remove_dir_all(&dst);
+                    }
+                    None
+                }
+                Ok(Some(stats)) => Some(stats),
+                Err(e) => {
+                    // 半同步的副本不可信，整个删除，让该模块本次不参与挂载
+                    log::error!("Failed to sync module {}: {:#}", module.id, e);
+                    let _ = fs::remove_dir_all(&dst);
+                    None
+                }
+            }
+        })
+        .collect();
 
-        let has_content = defs::BUILTIN_PARTITIONS.iter().any(|p| {
-            let part_path = module.source_path.join(p);
-            part_path.exists() && has_files_recursive(&part_path)
-        });
+    let mut total = SyncStats::default();
+    for stats in &results {
+        total.merge(stats);
+    }
 
-        // 传入 force 参数
-        if has_content && should_sync(&module.source_path, &dst, force) {
-            log::info!("Syncing module: {} (Reason: force={} or content update)", module.id, force);
+    log::info!(
+        ">> Sync done: {} modules changed, {} files ({} bytes) copied, {} removed, {} unchanged",
+        total.modules_synced,
+        total.files_copied,
+        total.bytes_copied,
+        total.files_removed,
+        total.files_unchanged
+    );
 
-            let tmp_dst = target_base.join(format!(".tmp_{}", module.id));
+    if config.sync.dedup {
+        let dedup = dedup::deduplicate(modules, target_base);
+        total.files_deduplicated = dedup.files;
+        total.bytes_deduplicated = dedup.bytes;
+    }
 
-            if tmp_dst.exists() {
-                let _ = fs::remove_dir_all(&tmp_dst);
+    Ok(total)
+}
+
+/// 只有可挂载分区下未被忽略的子树会被使用，模块根目录中仅保留 module.prop，
+/// Magic Mount 依靠它识别模块目录；webroot、脚本等其余内容不进入工作区
+fn is_mountable(module: &Module, partitions: &[String], relative: &str, is_dir: bool) -> bool {
+    let partition = relative.split('/').next().unwrap_or_default();
+
+    if partition == relative && !is_dir {
+        return relative == MODULE_PROP_FILE_NAME;
+    }
+
+    let mountable = defs::BUILTIN_PARTITIONS.contains(&partition)
+        || partitions.iter().any(|p| p == partition);
+
+    mountable && module.rules.get_mode(relative) != MountMode::Ignore
+}
+
+fn sync_options(config: &Config) -> SyncOptions {
+    SyncOptions {
+        repair: true,
+        xattrs: XattrFilter {
+            allow: config.sync.xattr_allow.clone(),
+            deny: config.sync.xattr_deny.clone(),
+        },
+    }
+}
+
+/// 同步单个模块，没有可挂载内容时返回 None
+fn sync_module(
+    module: &Module,
+    dst: &Path,
+    partitions: &[String],
+    opts: &SyncOptions,
+    force: bool,
+) -> Result<Option<SyncStats>> {
+    let mut stats = SyncStats::default();
+    let mut source = Manifest::scan(&module.source_path, |relative, ft| {
+        is_mountable(module, partitions, relative, ft.is_dir())
+    })?;
+
+    let has_content = source
+        .entries
+        .iter()
+        .any(|(relative, entry)| relative.contains('/') && entry.kind != EntryKind::Dir);
+    if !has_content {
+        return Ok(None);
+    }
+
+    let previous = match Manifest::load(dst) {
+        Some(manifest) if !force => manifest,
+        _ => {
+            // 没有可信清单时从空目录开始
+            if dst.exists() {
+                fs::remove_dir_all(dst)?;
             }
+            Manifest::default()
+        }
+    };
+    // 修改副本期间不保留旧清单，进程中断后下次会重新核对全部条目
+    Manifest::remove(dst);
+    fs::create_dir_all(dst)?;
+
+    // 先删除源中已不存在或类型改变的条目，倒序保证子条目先于父目录
+    for (relative, old) in previous.entries.iter().rev() {
+        if source
+            .entries
+            .get(relative)
+            .is_some_and(|entry| entry.kind == old.kind)
+        {
+            continue;
+        }
 
-            if let Err(e) = utils::sync_dir(&module.source_path, &tmp_dst, true) {
-                log::error!("Failed to sync module {}: {}", module.id, e);
-                let _ = fs::remove_dir_all(&tmp_dst);
-                return;
+        let path = dst.join(relative);
+        let removed = match fs::symlink_metadata(&path) {
+            Ok(m) if m.is_dir() => fs::remove_dir_all(&path).is_ok(),
+            Ok(_) => fs::remove_file(&path).is_ok(),
+            Err(_) => false,
+        };
+        if removed {
+            stats.files_removed += 1;
+        }
+    }
+
+    // 硬链接组中第一个已同步的副本，组内其余条目链接到它
+    let mut link_groups: HashMap<(u64, u64), PathBuf> = HashMap::new();
+
+    // 清单有序，父目录总先于子条目处理
+    for (relative, entry) in source.entries.iter_mut() {
+        let src_path = module.source_path.join(relative);
+        let dst_path = dst.join(relative);
+        let link_key = entry.link_key();
+
+        if let Some(old) = previous.entries.get(relative)
+            && fs::symlink_metadata(&dst_path).is_ok()
+        {
+            if entry.unchanged(old) {
+                entry.hash = old.hash.clone();
+                stats.files_unchanged += 1;
+                if let Some(key) = link_key {
+                    link_groups.entry(key).or_insert(dst_path);
+                }
+                continue;
             }
 
-            if let Err(e) = utils::prune_empty_dirs(&tmp_dst) {
-                log::warn!("Failed to prune empty dirs for {}: {}", module.id, e);
+            if entry.needs_hash(old) {
+                let new_hash = manifest::hash_file(&src_path)?;
+                let old_hash = match &old.hash {
+                    Some(hash) => hash.clone(),
+                    None => manifest::hash_file(&dst_path)?,
+                };
+                let same = new_hash == old_hash;
+                entry.hash = Some(new_hash);
+
+                // 去重后与其他模块共享 inode 的副本不能原地改时间，重新复制一份
+                if same && !shared_by_dedup(&dst_path, entry) {
+                    // 内容未变但修改时间变了，只需更新时间戳
+                    utils::copy_timestamps(&src_path, &dst_path)?;
+                    stats.files_unchanged += 1;
+                    if let Some(key) = link_key {
+                        link_groups.entry(key).or_insert(dst_path);
+                    }
+                    continue;
+                }
             }
+        }
+
+        if let Some(key) = link_key
+            && let Some(existing) = link_groups.get(&key)
+        {
+            utils::link_entry(existing, &dst_path)?;
+            stats.files_copied += 1;
+            continue;
+        }
+
+        stats.bytes_copied += utils::sync_entry(&src_path, &dst_path, Path::new(relative), opts)?;
+        if entry.kind != EntryKind::Dir {
+            stats.files_copied += 1;
+        }
+        if let Some(key) = link_key {
+            link_groups.insert(key, dst_path);
+        }
+    }
+
+    if stats.files_copied == 0 && stats.files_removed == 0 {
+        log::debug!("Module {} is up to date", module.id);
+    } else {
+        log::info!(
+            "Synced module {}: {} files ({} bytes) copied, {} removed",
+            module.id,
+            stats.files_copied,
+            stats.bytes_copied,
+            stats.files_removed
+        );
+        stats.modules_synced = 1;
+    }
+
+    if let Err(e) = utils::prune_empty_dirs(dst) {
+        log::warn!("Failed to prune empty dirs for {}: {}", module.id, e);
+    }
 
-            if let Err(e) = apply_overlay_opaque_flags(&tmp_dst) {
-                log::warn!(
-                    "Failed to apply overlay opaque xattrs for {}: {}",
-                    module.id,
-                    e
-                );
+    if let Err(e) = apply_overlay_opaque_flags(dst) {
+        log::warn!(
+            "Failed to apply overlay opaque xattrs for {}: {}",
+            module.id,
+            e
+        );
+    }
+
+    // 写入子条目与清理空目录都会改变目录的修改时间，最后自下而上还原
+    for (relative, entry) in source.entries.iter().rev() {
+        let dst_path = dst.join(relative);
+        if entry.kind == EntryKind::Dir && dst_path.exists() {
+            utils::copy_timestamps(&module.source_path.join(relative), &dst_path)
+                .unwrap_or_else(|e| log::debug!("{:#}", e));
+        }
+    }
+
+    // 清单最后写入，中途失败时不会留下与副本不符的清单
+    source.save(dst)?;
+
+    Ok(Some(stats))
+}
+
+/// 副本的链接数多于源文件，说明它被去重链接到了其他模块的副本
+fn shared_by_dedup(dst_path: &Path, entry: &Entry) -> bool {
+    entry.kind == EntryKind::File
+        && fs::symlink_metadata(dst_path).is_ok_and(|m| m.nlink() > entry.nlink)
+}
+
+/// 单个模块最多逐条报告的不一致数
+const VERIFY_REPORT_LIMIT: usize = 20;
+
+/// 逐条对比源目录与同步副本的元数据，每个模块的不一致汇总为诊断
+/// SELinux 标签会按系统路径重新计算，不参与比较
+pub fn verify(modules: &[Module], target_base: &Path, config: &Config) -> Vec<DiagnosticIssue> {
+    let filter = sync_options(config).xattrs;
+
+    modules
+        .par_iter()
+        .flat_map(|module| {
+            let mismatches = verify_module(module, &target_base.join(&module.id), &filter);
+            let total = mismatches.len();
+
+            let mut issues: Vec<DiagnosticIssue> = mismatches
+                .into_iter()
+                .take(VERIFY_REPORT_LIMIT)
+                .map(|message| DiagnosticIssue {
+                    level: DiagnosticLevel::Warning,
+                    context: module.id.clone(),
+                    message: format!("Sync verification: {}", message),
+                })
+                .collect();
+
+            if total > VERIFY_REPORT_LIMIT {
+                issues.push(DiagnosticIssue {
+                    level: DiagnosticLevel::Warning,
+                    context: module.id.clone(),
+                    message: format!(
+                        "Sync verification: {} more mismatches not shown",
+                        total - VERIFY_REPORT_LIMIT
+                    ),
+                });
             }
+            issues
+        })
+        .collect()
+}
+
+fn verify_module(module: &Module, dst: &Path, filter: &XattrFilter) -> Vec<String> {
+    // 没有清单说明模块未被同步（无可挂载内容），无需校验
+    let Some(manifest) = Manifest::load(dst) else {
+        return Vec::new();
+    };
+
+    let mut mismatches = Vec::new();
+    // 源端硬链接组 -> 组内第一个条目及其副本的 inode
+    let mut link_groups: HashMap<(u64, u64), (&str, u64)> = HashMap::new();
 
-            if dst.exists()
-                && let Err(e) = fs::remove_dir_all(&dst)
-            {
-                log::warn!(
-                    "Failed to clean existing target dir for {}: {}",
-                    module.id,
-                    e
-                );
+    for (relative, recorded) in &manifest.entries {
+        let Ok(source) = Entry::from_path(&module.source_path.join(relative)) else {
+            mismatches.push(format!("{}: removed from source since the last sync", relative));
+            continue;
+        };
+        let Ok(copy) = Entry::from_path(&dst.join(relative)) else {
+            // 空目录会在同步后被清理
+            if recorded.kind != EntryKind::Dir {
+                mismatches.push(format!("{}: missing from the copy", relative));
             }
+            continue;
+        };
 
-            if let Err(e) = fs::rename(&tmp_dst, &dst) {
-                log::error!("Failed to commit atomic sync for {}: {}", module.id, e);
-                let _ = fs::remove_dir_all(&tmp_dst);
+        let mut fields = diff_entry(&source, &copy, filter);
+
+        if let Some(key) = source.link_key() {
+            match link_groups.get(&key) {
+                Some((first, ino)) if *ino != copy.ino => {
+                    fields.push(format!("hardlink to {}", first));
+                }
+                Some(_) => {}
+                None => {
+                    link_groups.insert(key, (relative.as_str(), copy.ino));
+                }
             }
-        } else {
-            log::debug!("Skipping module: {}", module.id);
         }
-    });
 
-    Ok(())
+        if !fields.is_empty() {
+            mismatches.push(format!("{}: {} differ", relative, fields.join(", ")));
+        }
+    }
+
+    mismatches
+}
+
+fn diff_entry(source: &Entry, copy: &Entry, filter: &XattrFilter) -> Vec<String> {
+    let mut fields = Vec::new();
+
+    if source.kind != copy.kind {
+        fields.push("type".to_string());
+        return fields;
+    }
+    if source.size != copy.size {
+        fields.push("size".to_string());
+    }
+    if source.kind != EntryKind::Symlink && source.mode != copy.mode {
+        fields.push("mode".to_string());
+    }
+    if source.uid != copy.uid || source.gid != copy.gid {
+        fields.push("owner".to_string());
+    }
+    if (source.mtime, source.mtime_nsec) != (copy.mtime, copy.mtime_nsec) {
+        fields.push("mtime".to_string());
+    }
+    if source.target != copy.target {
+        fields.push("link target".to_string());
+    }
+    if source.rdev != copy.rdev {
+        fields.push("device number".to_string());
+    }
+
+    for (name, value) in &source.xattrs {
+        if name != SELINUX_XATTR && filter.allows(name) && copy.xattrs.get(name) != Some(value) {
+            fields.push(format!("xattr {}", name));
+        }
+    }
+
+    fields
 }
 
 fn apply_overlay_opaque_flags(root: &Path) -> Result<()> {
     for entry in WalkDir::new(root).min_depth(1).into_iter().flatten() {
-        if entry.file_type().is_file() && entry.file_name() == defs::REPLACE_DIR_FILE_NAME {
-            if let Some(parent) = entry.path().parent() {
-                utils::set_overlay_opaque(parent)?;
-                log::debug!("Set overlay opaque xattr on: {}", parent.display());
-            }
+        if entry.file_type().is_file()
+            && entry.file_name() == defs::REPLACE_DIR_FILE_NAME
+            && let Some(parent) = entry.path().parent()
+        {
+            utils::set_overlay_opaque(parent)?;
+            log::debug!("Set overlay opaque xattr on: {}", parent.display());
         }
     }
     Ok(())
@@ -115,34 +493,3 @@ fn prune_orphaned_modules(modules: &[Module], target_base: &Path) -> Result<()>
 
     Ok(())
 }
-
-fn should_sync(src: &Path, dst: &Path, force: bool) -> bool {
-    // 如果强制同步标志为真（模式切换），或目标不存在，直接返回 true
-    if force || !dst.exists() {
-        return true;
-    }
-
-    let src_prop = src.join("module.prop");
-    let dst_prop = dst.join("module.prop");
-
-    if !src_prop.exists() || !dst_prop.exists() {
-        return true;
-    }
-
-    // 仅在非强制模式下才执行内容对比
-    match (fs::read(&src_prop), fs::read(&dst_prop)) {
-        (Ok(s), Ok(d)) => s != d,
-        _ => true,
-    }
-}
-
-fn has_files_recursive(path: &Path) -> bool {
-    if let Ok(entries) = fs::read_dir(path) {
-        for entry in entries.flatten() {
-            if entry.file_type().is_ok() {
-                return true;
-            }
-        }
-    }
-    false
-}
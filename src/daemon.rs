@@ -0,0 +1,451 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, SyncSender},
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    conf::{cli::Cli, cli_handlers, config::Config, validate},
+    core::{granary, inventory::ModuleRules, modules, rescue, state::RuntimeState, storage},
+    defs,
+};
+
+const STATE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// 单次写入的超时，客户端长时间不读取时断开连接
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// 每个连接待发送的行数上限，推送排满时丢弃该订阅者
+const OUTBOX_CAPACITY: usize = 64;
+
+/// JSON-RPC 方法及其参数
+#[derive(Deserialize, Debug)]
+#[serde(tag = "method", content = "params")]
+enum Method {
+    #[serde(rename = "config.get")]
+    ConfigGet,
+    #[serde(rename = "config.set")]
+    ConfigSet { config: Box<Config> },
+    #[serde(rename = "config.validate")]
+    ConfigValidate { config: Option<Box<Config>> },
+    #[serde(rename = "modules.list")]
+    ModulesList,
+    #[serde(rename = "modules.save_rules")]
+    ModulesSaveRules { module: String, rules: ModuleRules },
+    #[serde(rename = "conflicts")]
+    Conflicts,
+    #[serde(rename = "diagnostics")]
+    Diagnostics,
+    #[serde(rename = "storage")]
+    Storage,
+    #[serde(rename = "granary.list")]
+    GranaryList,
+    #[serde(rename = "granary.create")]
+    GranaryCreate { reason: Option<String> },
+    #[serde(rename = "granary.delete")]
+    GranaryDelete { id: String },
+    #[serde(rename = "granary.restore")]
+    GranaryRestore { id: String },
+    #[serde(rename = "rescue")]
+    Rescue,
+    #[serde(rename = "status")]
+    Status,
+    #[serde(rename = "status.subscribe")]
+    StatusSubscribe,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct Notification<'a, T: Serialize> {
+    jsonrpc: &'static str,
+    method: &'a str,
+    params: T,
+}
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const INTERNAL_ERROR: i32 = -32603;
+
+/// 单个连接的发送队列：响应与推送都交给同一个写线程逐行写出，不会互相穿插
+#[derive(Clone)]
+struct Outbox {
+    id: u64,
+    sender: SyncSender<String>,
+}
+
+impl Outbox {
+    fn open(id: u64, stream: &UnixStream) -> Result<(Self, thread::JoinHandle<()>)> {
+        let mut writer = stream.try_clone()?;
+        writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        let (sender, receiver) = mpsc::sync_channel::<String>(OUTBOX_CAPACITY);
+        let handle = thread::spawn(move || {
+            for line in receiver {
+                if let Err(e) = writer.write_all(line.as_bytes()) {
+                    log::debug!("Daemon client write failed: {}", e);
+                    // 同时结束读取端，让 serve_client 退出
+                    let _ = writer.shutdown(Shutdown::Both);
+                    break;
+                }
+            }
+        });
+
+        Ok((Self { id, sender }, handle))
+    }
+
+    /// 排队发送响应，队列满时等待写线程
+    fn send(&self, line: String) -> Result<()> {
+        self.sender
+            .send(line)
+            .map_err(|_| anyhow::anyhow!("Daemon client writer closed"))
+    }
+
+    /// 不阻塞地排队推送，队列已满或连接已关闭时返回 false
+    fn push(&self, line: String) -> bool {
+        self.sender.try_send(line).is_ok()
+    }
+}
+
+struct Daemon {
+    /// 启动守护进程时的命令行，每次请求都按它重新加载生效配置
+    cli: Cli,
+    subscribers: Mutex<Vec<Outbox>>,
+    write_lock: Mutex<()>,
+    next_client: AtomicU64,
+}
+
+impl Daemon {
+    fn call(&self, method: Method, outbox: &Outbox) -> Result<Value> {
+        let config = || cli_handlers::load_config(&self.cli);
+
+        let result = match method {
+            Method::ConfigGet => serde_json::to_value(config()?)?,
+            Method::ConfigSet { config: new_config } => {
+                {
+                    let _guard = self.lock_writes()?;
+                    cli_handlers::save_config(&self.cli, &new_config)?;
+                }
+                // 订阅者收到的应与 config.get 一致：叠加 config.d 与 profile 后的生效配置
+                if let Ok(effective) = config() {
+                    self.broadcast("config.changed", &effective);
+                }
+                json!(true)
+            }
+            Method::ConfigValidate { config: candidate } => {
+                let candidate = match candidate {
+                    Some(c) => *c,
+                    None => config()?,
+                };
+                serde_json::to_value(validate::validate(&candidate))?
+            }
+            Method::ModulesList => serde_json::to_value(modules::list(&config()?)?)?,
+            Method::ModulesSaveRules { module, rules } => {
+                let _guard = self.lock_writes()?;
+                cli_handlers::save_module_rules(&module, &serde_json::to_vec(&rules)?)?;
+                json!(true)
+            }
+            Method::Conflicts => serde_json::to_value(cli_handlers::conflicts_report(&config()?)?)?,
+            Method::Diagnostics => {
+                serde_json::to_value(cli_handlers::diagnostics_report(&config()?)?)?
+            }
+            Method::Storage => serde_json::to_value(storage::status())?,
+            Method::GranaryList => serde_json::to_value(granary::list_snapshots()?)?,
+            Method::GranaryCreate { reason } => {
+                let _guard = self.lock_writes()?;
+                let reason = reason.as_deref().unwrap_or("Manual Backup");
+                json!(granary::create_snapshot(
+                    &config()?,
+                    "Manual Snapshot",
+                    reason
+                )?)
+            }
+            Method::GranaryDelete { id } => {
+                let _guard = self.lock_writes()?;
+                granary::delete_snapshot(&id)?;
+                json!(true)
+            }
+            Method::GranaryRestore { id } => {
+                {
+                    let _guard = self.lock_writes()?;
+                    granary::restore_snapshot(&id)?;
+                }
+                if let Ok(restored) = config() {
+                    self.broadcast("config.changed", &restored);
+                }
+                json!(true)
+            }
+            Method::Rescue => serde_json::to_value(rescue::report())?,
+            Method::Status => serde_json::to_value(RuntimeState::load().unwrap_or_default())?,
+            Method::StatusSubscribe => {
+                self.subscribers
+                    .lock()
+                    .map_err(|_| anyhow::anyhow!("Failed to lock subscribers"))?
+                    .push(outbox.clone());
+                serde_json::to_value(RuntimeState::load().unwrap_or_default())?
+            }
+        };
+
+        Ok(result)
+    }
+
+    fn lock_writes(&self) -> Result<std::sync::MutexGuard<'_, ()>> {
+        self.write_lock
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock daemon writes"))
+    }
+
+    /// 向所有订阅者推送事件，只排队不写入；队列已满或已断开的订阅者会被移除
+    fn broadcast<T: Serialize>(&self, method: &str, params: &T) {
+        let notification = Notification {
+            jsonrpc: "2.0",
+            method,
+            params,
+        };
+
+        let Ok(mut line) = serde_json::to_string(&notification) else {
+            return;
+        };
+        line.push('\n');
+
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|s| s.push(line.clone()));
+        }
+    }
+
+    fn is_subscribed(&self, id: u64) -> bool {
+        self.subscribers
+            .lock()
+            .is_ok_and(|subscribers| subscribers.iter().any(|s| s.id == id))
+    }
+
+    fn unsubscribe(&self, id: u64) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|s| s.id != id);
+        }
+    }
+
+    fn handle_line(&self, line: &str, outbox: &Outbox) -> Response {
+        let mut response = Response {
+            jsonrpc: "2.0",
+            id: Value::Null,
+            result: None,
+            error: None,
+        };
+
+        let request: Request = match serde_json::from_str(line) {
+            Ok(r) => r,
+            Err(e) => {
+                response.error = Some(RpcError {
+                    code: PARSE_ERROR,
+                    message: e.to_string(),
+                });
+                return response;
+            }
+        };
+        response.id = request.id;
+
+        let mut call = json!({ "method": request.method });
+        if !request.params.is_null() {
+            call["params"] = request.params;
+        }
+
+        let method: Method = match serde_json::from_value(call) {
+            Ok(m) => m,
+            Err(e) => {
+                let message = e.to_string();
+                let code = if message.starts_with("unknown variant") {
+                    METHOD_NOT_FOUND
+                } else {
+                    INVALID_PARAMS
+                };
+                response.error = Some(RpcError { code, message });
+                return response;
+            }
+        };
+
+        log::debug!("RPC: {:?}", method);
+
+        match self.call(method, outbox) {
+            Ok(result) => response.result = Some(result),
+            Err(e) => {
+                response.error = Some(RpcError {
+                    code: INTERNAL_ERROR,
+                    message: format!("{:#}", e),
+                })
+            }
+        }
+
+        response
+    }
+
+    fn serve_client(&self, stream: UnixStream) -> Result<()> {
+        let id = self.next_client.fetch_add(1, Ordering::Relaxed);
+        let (outbox, writer) = Outbox::open(id, &stream)?;
+
+        let result = self.read_requests(&stream, &outbox);
+
+        // `meta-hybrid rpc` 在标准输入结束后关闭写端，订阅者仍需继续接收推送，
+        // 直到写入失败（客户端已退出）后写线程结束
+        if result.is_err() || !self.is_subscribed(id) {
+            self.unsubscribe(id);
+        }
+        drop(outbox);
+        let _ = writer.join();
+        self.unsubscribe(id);
+
+        result
+    }
+
+    fn read_requests(&self, stream: &UnixStream, outbox: &Outbox) -> Result<()> {
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = self.handle_line(&line, outbox);
+            let mut out = serde_json::to_string(&response)?;
+            out.push('\n');
+            outbox.send(out)?;
+        }
+
+        Ok(())
+    }
+
+    /// 监视 daemon_state.json，变化时推送 status.changed
+    fn watch_state(&self) {
+        let mut last_modified: Option<SystemTime> = None;
+
+        loop {
+            let modified = fs::metadata(defs::STATE_FILE)
+                .and_then(|m| m.modified())
+                .ok();
+
+            if modified.is_some() && modified != last_modified {
+                last_modified = modified;
+                if let Ok(state) = RuntimeState::load() {
+                    self.broadcast("status.changed", &state);
+                }
+            }
+
+            thread::sleep(STATE_POLL_INTERVAL);
+        }
+    }
+}
+
+/// 常驻服务：在 Unix Socket 上提供 JSON-RPC 接口
+pub fn run(cli: &Cli) -> Result<()> {
+    let socket_path = Path::new(defs::DAEMON_SOCKET);
+
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            bail!("Daemon already running at {}", socket_path.display());
+        }
+        fs::remove_file(socket_path).context("Failed to remove stale daemon socket")?;
+    }
+
+    if let Some(parent) = socket_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)
+        .with_context(|| format!("Failed to bind {}", socket_path.display()))?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+
+    log::info!(">> Daemon listening on {}", socket_path.display());
+
+    let daemon = Arc::new(Daemon {
+        cli: cli.clone(),
+        subscribers: Mutex::new(Vec::new()),
+        write_lock: Mutex::new(()),
+        next_client: AtomicU64::new(0),
+    });
+
+    {
+        let daemon = daemon.clone();
+        thread::spawn(move || daemon.watch_state());
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let daemon = daemon.clone();
+                thread::spawn(move || {
+                    if let Err(e) = daemon.serve_client(stream) {
+                        log::debug!("Daemon client disconnected: {}", e);
+                    }
+                });
+            }
+            Err(e) => log::warn!("Daemon accept failed: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+/// 将标准输入中的请求逐行转发给守护进程，并输出其响应
+pub fn forward_stdin() -> Result<()> {
+    let stream = UnixStream::connect(defs::DAEMON_SOCKET)
+        .with_context(|| format!("Failed to connect to {}", defs::DAEMON_SOCKET))?;
+
+    let reader = stream.try_clone()?;
+    let printer = thread::spawn(move || -> io::Result<()> {
+        let stdout = io::stdout();
+        for line in BufReader::new(reader).lines() {
+            writeln!(stdout.lock(), "{}", line?)?;
+        }
+        Ok(())
+    });
+
+    let mut writer = &stream;
+    io::copy(&mut io::stdin().lock(), &mut writer)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    printer
+        .join()
+        .map_err(|_| anyhow::anyhow!("Daemon reader thread panicked"))??;
+
+    Ok(())
+}
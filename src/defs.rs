@@ -4,10 +4,13 @@
 pub const DEFAULT_HYBRID_MNT_DIR: &str = "/mnt/vendor/meta-hybrid";
 pub const RUN_DIR: &str = "/dev/meta-hybrid/run/";
 pub const STATE_FILE: &str = "/dev/meta-hybrid/run/daemon_state.json";
+pub const DAEMON_SOCKET: &str = "/dev/meta-hybrid/run/daemon.sock";
 pub const DISABLE_FILE_NAME: &str = "disable";
 pub const REMOVE_FILE_NAME: &str = "remove";
 pub const SKIP_MOUNT_FILE_NAME: &str = "skip_mount";
 
+pub const MAGIC_WORKSPACE_DIR: &str = "magic_workspace";
+
 // 统一使用 /dev 下的目录作为 Overlay 的 RW 支撑
 pub const SYSTEM_RW_DIR: &str = "/dev/meta-hybrid/rw";
 
@@ -63,3 +66,4 @@ pub const SENSITIVE_PARTITIONS: &[&str] = &[
 
 pub const REPLACE_DIR_FILE_NAME: &str = ".replace";
 pub const REPLACE_DIR_XATTR: &str = "trusted.overlay.opaque";
+pub const SYNC_MANIFEST_FILE_NAME: &str = ".hybrid_manifest.json";
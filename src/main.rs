@@ -3,8 +3,11 @@
 
 mod conf;
 mod core;
+mod daemon;
 mod defs;
 mod mount;
+mod props;
+mod selinux;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 mod try_umount;
 mod utils;
@@ -24,41 +27,24 @@ use mimalloc::MiMalloc;
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
-fn load_config(cli: &Cli) -> Result<Config> {
-    if let Some(config_path) = &cli.config {
-        return Config::from_file(config_path).with_context(|| {
-            format!(
-                "Failed to load config from custom path: {}",
-                config_path.display()
-            )
-        });
+/// 启动时配置文件损坏也要继续挂载，此时退回默认值（仍应用命令行覆盖）
+/// 旧版本配置文件只在这里（以及 migrate-config 与写配置时）原地升级
+fn load_final_config(cli: &Cli) -> Result<Config> {
+    let path = cli
+        .config
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(conf::config::CONFIG_FILE_DEFAULT));
+
+    if path.exists()
+        && let Err(e) = conf::migrate::upgrade_in_place(&path)
+    {
+        utils::log_early(
+            log::Level::Warn,
+            format!("Failed to upgrade config {}: {:#}", path.display(), e),
+        );
     }
 
-    Ok(Config::load_default().unwrap_or_else(|e| {
-        let is_not_found = e
-            .root_cause()
-            .downcast_ref::<std::io::Error>()
-            .map(|io_err| io_err.kind() == std::io::ErrorKind::NotFound)
-            .unwrap_or(false);
-
-        if is_not_found {
-            Config::default()
-        } else {
-            log::warn!("Failed to load default config, using defaults: {}", e);
-            Config::default()
-        }
-    }))
-}
-
-fn load_final_config(cli: &Cli) -> Result<Config> {
-    let mut config = load_config(cli)?;
-    config.merge_with_cli(
-        cli.moduledir.clone(),
-        cli.mountsource.clone(),
-        cli.verbose,
-        cli.partitions.clone(),
-    );
-    Ok(config)
+    cli_handlers::load_config_or_default(cli)
 }
 
 fn main() -> Result<()> {
@@ -76,15 +62,33 @@ fn main() -> Result<()> {
     if let Some(command) = &cli.command {
         match command {
             Commands::GenConfig { output } => cli_handlers::handle_gen_config(output)?,
-            Commands::ShowConfig => cli_handlers::handle_show_config(&cli)?,
+            Commands::ShowConfig { origins } => cli_handlers::handle_show_config(&cli, *origins)?,
+            Commands::MigrateConfig { dry_run } => {
+                cli_handlers::handle_migrate_config(&cli, *dry_run)?
+            }
+            Commands::ValidateConfig => cli_handlers::handle_validate_config(&cli)?,
+            Commands::Config { action } => cli_handlers::handle_config(&cli, action)?,
+            Commands::Schema { target } => cli_handlers::handle_schema(*target)?,
             Commands::SaveConfig { payload } => cli_handlers::handle_save_config(&cli, payload)?,
             Commands::SaveModuleRules { module, payload } => {
                 cli_handlers::handle_save_module_rules(module, payload)?
             }
             Commands::Storage => cli_handlers::handle_storage()?,
+            Commands::VerifySync => cli_handlers::handle_verify_sync(&cli)?,
             Commands::Modules => cli_handlers::handle_modules(&cli)?,
             Commands::Conflicts => cli_handlers::handle_conflicts(&cli)?,
             Commands::Diagnostics => cli_handlers::handle_diagnostics(&cli)?,
+            Commands::Plan { tree } => cli_handlers::handle_plan(&cli, *tree)?,
+            Commands::Explain { path } => cli_handlers::handle_explain(&cli, path)?,
+            Commands::BootWatch { timeout, prop_file } => {
+                cli_handlers::handle_boot_watch(&cli, *timeout, prop_file.as_deref())?
+            }
+            Commands::Daemon => cli_handlers::handle_daemon(&cli)?,
+            Commands::Rpc => cli_handlers::handle_rpc()?,
+            Commands::Rescue { abort } => cli_handlers::handle_rescue(*abort)?,
+            Commands::SelinuxLabel { path, kind } => {
+                cli_handlers::handle_selinux_label(path, *kind)?
+            }
             Commands::SystemAction { action, value } => {
                 cli_handlers::handle_system_action(&cli, action, value.as_deref())?
             }
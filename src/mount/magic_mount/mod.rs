@@ -1,10 +1,10 @@
 // Copyright 2026 Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-mod utils;
+pub mod utils;
 
 use std::{
-    collections::HashSet,
+    collections::HashMap,
     fs,
     path::{Path, PathBuf},
     sync::atomic::AtomicU32,
@@ -19,12 +19,13 @@ use rustix::mount::{
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::try_umount::send_umountable;
 use crate::{
+    core::planner::MagicWork,
     mount::{
         magic_mount::utils::{clone_symlink, collect_module_files, mount_mirror},
         node::{Node, NodeFileType},
     },
-    try_umount,
-    utils::ensure_dir_exists,
+    selinux::{self, FileKind},
+    utils::{ensure_dir_exists, lsetfilecon},
 };
 
 static MOUNTED_FILES: AtomicU32 = AtomicU32::new(0);
@@ -88,6 +89,12 @@ impl MagicMount {
                     self.work_dir_path.display(),
                 )
             })?;
+            // 新增的软链接没有原厂标签可参考，按 file_contexts 计算
+            if self.path.symlink_metadata().is_err()
+                && let Some(ctx) = selinux::lookup(&self.path, FileKind::Symlink)
+            {
+                lsetfilecon(&self.work_dir_path, &ctx).ok();
+            }
             let mounted = MOUNTED_SYMBOLS_FILES.load(std::sync::atomic::Ordering::Relaxed) + 1;
             MOUNTED_SYMBOLS_FILES.store(mounted, std::sync::atomic::Ordering::Relaxed);
             Ok(())
@@ -307,14 +314,14 @@ pub fn magic_mount<P>(
     module_dir: &Path,
     mount_source: &str,
     extra_partitions: &[String],
-    need_id: HashSet<String>,
+    need: &HashMap<String, MagicWork>,
     #[cfg(any(target_os = "linux", target_os = "android"))] umount: bool,
     #[cfg(not(any(target_os = "linux", target_os = "android")))] _umount: bool,
 ) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    if let Some(root) = collect_module_files(module_dir, extra_partitions, need_id)? {
+    if let Some(root) = collect_module_files(module_dir, extra_partitions, need)? {
         log::debug!("collected: {root:?}");
         let tmp_root = tmp_path.as_ref();
         let tmp_dir = tmp_root.join("workdir");
@@ -341,8 +348,6 @@ where
         if let Err(e) = unmount(&tmp_dir, UnmountFlags::DETACH) {
             log::error!("failed to unmount tmp {e}");
         }
-        #[cfg(any(target_os = "android", target_os = "linux"))]
-        try_umount::commit()?;
         fs::remove_dir(tmp_dir).ok();
 
         let mounted_symbols = MOUNTED_SYMBOLS_FILES.load(std::sync::atomic::Ordering::Relaxed);
@@ -2,39 +2,26 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashSet,
-    fs::{self, DirEntry, Metadata, create_dir, create_dir_all, read_link},
+    collections::{HashMap, HashSet, hash_map::Entry},
+    fs::{self, DirEntry, create_dir, create_dir_all, read_link},
     os::unix::fs::{MetadataExt, symlink},
-    path::{Path, PathBuf},
+    path::Path,
 };
 
-use anyhow::{Result, bail, Context};
+use anyhow::{Result, bail};
 use rustix::{
     fs::{Gid, Mode, Uid, chmod, chown},
     mount::mount_bind,
 };
 
 use crate::{
+    core::{inventory::MountMode, planner::MagicWork},
     defs::{DISABLE_FILE_NAME, REMOVE_FILE_NAME, SKIP_MOUNT_FILE_NAME},
-    mount::node::Node,
-    utils::{lgetfilecon, lsetfilecon, validate_module_id, detect_all_partitions},
+    mount::node::{Node, NodeFileType},
+    selinux::{self, FileKind},
+    utils::{lgetfilecon, lsetfilecon, detect_all_partitions},
 };
 
-/// 获取元数据和路径参考
-fn metadata_path<P>(path: P, node: &Node) -> Result<(Metadata, PathBuf)>
-where
-    P: AsRef<Path>,
-{
-    let path = path.as_ref();
-    if path.exists() {
-        Ok((path.metadata()?, path.to_path_buf()))
-    } else if let Some(module_path) = &node.module_path {
-        Ok((module_path.metadata()?, module_path.clone()))
-    } else {
-        bail!("cannot mount root dir {}!", path.display());
-    }
-}
-
 /// 构建 tmpfs 骨架并同步 SELinux 标签
 /// 这是防止字体模块等关键组件 Bootloop 的核心逻辑
 pub fn tmpfs_skeleton<P>(path: P, work_dir_path: P, node: &Node) -> Result<()>
@@ -65,7 +52,13 @@ where
 
     // 关键：同步 SELinux 标签
     // 确保 tmpfs 目录不被标记为 ksu_file，否则系统进程无法读取
-    if let Ok(ctx) = lgetfilecon(&ref_path) {
+    // 系统中不存在的新目录按 file_contexts 计算标签
+    let ctx = if path.exists() {
+        lgetfilecon(&ref_path).ok()
+    } else {
+        selinux::lookup(path, FileKind::Directory).or_else(|| lgetfilecon(&ref_path).ok())
+    };
+    if let Some(ctx) = ctx {
         lsetfilecon(work_dir_path, &ctx).ok();
     }
 
@@ -113,28 +106,41 @@ where
 
 /// 动态收集模块文件并构建节点树
 /// 移除硬编码，改用 detect_all_partitions 进行动态探测
+/// need 为每个模块需要 Magic Mount 的子树（模块内相对路径）及其规则，只为这些子树建立节点
 pub fn collect_module_files(
     module_dir: &Path,
     user_extra_partitions: &[String],
-    need_id: HashSet<String>,
+    need: &HashMap<String, MagicWork>,
 ) -> Result<Option<Node>> {
     let mut root = Node::new_root("");
     let mut system = Node::new_root("system");
     let mut has_file = HashSet::new();
 
-    // 1. 动态获取系统当前所有分区列表
+    // 1. 动态获取系统当前所有分区列表，并补上计划中涉及的分区
     let mut all_partitions = detect_all_partitions().unwrap_or_default();
     all_partitions.extend(user_extra_partitions.iter().cloned());
+    for work in need.values() {
+        all_partitions.extend(work.paths.iter().filter_map(|p| p.split('/').next().map(str::to_string)));
+    }
     all_partitions.sort();
     all_partitions.dedup();
 
     log::debug!("Dynamic partition detection result: {:?}", all_partitions);
 
+    // 先收集的节点在合并时胜出，因此按模块优先级而非 read_dir 顺序遍历
+    let mut entries: Vec<(DirEntry, &MagicWork)> = Vec::new();
     for entry in module_dir.read_dir()?.flatten() {
         if !entry.file_type()?.is_dir() { continue; }
 
         let id = entry.file_name().to_string_lossy().to_string();
-        if !need_id.contains(&id) { continue; }
+        if let Some(work) = need.get(&id) {
+            entries.push((entry, work));
+        }
+    }
+    entries.sort_by_key(|(entry, work)| (work.rank, entry.file_name()));
+
+    for (entry, work) in entries {
+        let id = entry.file_name().to_string_lossy().to_string();
 
         let prop = entry.path().join("module.prop");
         if !prop.exists() { continue; }
@@ -147,27 +153,23 @@ pub fn collect_module_files(
             continue;
         }
 
-        // 2. 检查模块是否修改了任何探测到的分区
-        let mut modified = false;
-        for p in &all_partitions {
-            if entry.path().join(p).is_dir() {
-                modified = true;
-                break;
-            }
-        }
-
-        if !modified { continue; }
-
-        // 3. 将模块修改的文件收集到虚拟 system 节点中
-        for p in &all_partitions {
-            let part_path = entry.path().join(p);
-            if !part_path.exists() { continue; }
-            has_file.insert(system.collect_module_files(part_path)?);
+        // 2. 只将计划中的子树收集到虚拟 system 节点中
+        for sub_path in &work.paths {
+            let source = entry.path().join(sub_path);
+            if !source.is_dir() { continue; }
+            if work.rules.get_mode(sub_path) == MountMode::Ignore { continue; }
+
+            let Some(node) = descend(&mut system, &entry.path(), sub_path) else {
+                log::warn!("Cannot place magic subtree {}/{}", id, sub_path);
+                continue;
+            };
+            let collected = node.collect_module_files(&source, sub_path, &work.rules)?;
+            has_file.insert(collected || node.replace);
         }
     }
 
     if has_file.contains(&true) {
-        // 4. 将独立物理分区从 system 节点移动到 root 节点
+        // 3. 将独立物理分区从 system 节点移动到 root 节点
         for partition in all_partitions {
             if partition == "system" { continue; }
 
@@ -175,11 +177,12 @@ pub fn collect_module_files(
             let path_of_system = Path::new("/system").join(&partition);
 
             // 如果该分区挂载在根目录，且在 /system 下是软链接或不存在，则它是一个独立分区
-            if path_of_root.is_dir() && (!path_of_system.exists() || path_of_system.is_symlink()) {
-                if let Some(node) = system.children.remove(&partition) {
-                    log::debug!("Detaching partition '{}' from system and attaching to root", partition);
-                    root.children.insert(partition, node);
-                }
+            if path_of_root.is_dir()
+                && (!path_of_system.exists() || path_of_system.is_symlink())
+                && let Some(node) = system.children.remove(&partition)
+            {
+                log::debug!("Detaching partition '{}' from system and attaching to root", partition);
+                root.children.insert(partition, node);
             }
         }
 
@@ -190,6 +193,37 @@ pub fn collect_module_files(
     }
 }
 
+/// 沿模块内相对路径逐层取得（或建立）目录节点，system 分区对应 system 节点本身
+/// 中间目录只作为路径存在，不继承 replace 标记，否则会替换掉子树之外的内容
+fn descend<'a>(system: &'a mut Node, module_root: &Path, sub_path: &str) -> Option<&'a mut Node> {
+    let segments: Vec<&str> = sub_path.split('/').filter(|s| !s.is_empty()).collect();
+    let (start, rest) = match segments.split_first() {
+        Some((&"system", rest)) => (1, rest),
+        Some(_) => (0, &segments[..]),
+        None => return None,
+    };
+
+    let mut current = system;
+    for (i, name) in rest.iter().enumerate() {
+        let depth = start + i + 1;
+        let node = match current.children.entry(name.to_string()) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => {
+                let mut node = Node::new_module_path(name, &module_root.join(segments[..depth].join("/")))?;
+                node.replace &= depth == segments.len();
+                v.insert(node)
+            }
+        };
+
+        if node.file_type != NodeFileType::Directory {
+            return None;
+        }
+        current = node;
+    }
+
+    Some(current)
+}
+
 /// 镜像软链接并还原其标签
 pub fn clone_symlink<S>(src: S, dst: S) -> Result<()>
 where
@@ -12,7 +12,10 @@ use std::{
 use anyhow::Result;
 use extattr::lgetxattr;
 
-use crate::defs::{REPLACE_DIR_FILE_NAME, REPLACE_DIR_XATTR};
+use crate::{
+    core::inventory::{ModuleRules, MountMode},
+    defs::{REPLACE_DIR_FILE_NAME, REPLACE_DIR_XATTR},
+};
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum NodeFileType {
@@ -57,7 +60,13 @@ impl fmt::Display for Node {
 }
 
 impl Node {
-    pub fn collect_module_files<P>(&mut self, module_dir: P) -> Result<bool>
+    /// relative_path 为 module_dir 在模块内的相对路径，规则为 ignore 的子项会被跳过
+    pub fn collect_module_files<P>(
+        &mut self,
+        module_dir: P,
+        relative_path: &str,
+        rules: &ModuleRules,
+    ) -> Result<bool>
     where
         P: AsRef<Path>,
     {
@@ -65,6 +74,12 @@ impl Node {
         let mut has_file = false;
         for entry in dir.read_dir()?.flatten() {
             let name = entry.file_name().to_string_lossy().to_string();
+            let child_path = format!("{}/{}", relative_path, name);
+
+            if rules.get_mode(&child_path) == MountMode::Ignore {
+                log::debug!("{} is ignored by module rules", child_path);
+                continue;
+            }
 
             let node = match self.children.entry(name.clone()) {
                 Entry::Occupied(o) => Some(o.into_mut()),
@@ -73,7 +88,8 @@ impl Node {
 
             if let Some(node) = node {
                 has_file |= if node.file_type == NodeFileType::Directory {
-                    node.collect_module_files(dir.join(&node.name))? || node.replace
+                    node.collect_module_files(dir.join(&node.name), &child_path, rules)?
+                        || node.replace
                 } else {
                     true
                 }
@@ -114,15 +130,21 @@ impl Node {
     where
         S: ToString,
     {
-        if let Ok(metadata) = entry.metadata() {
-            let path = entry.path();
+        Self::new_module_path(name, &entry.path())
+    }
+
+    pub fn new_module_path<S>(name: &S, path: &Path) -> Option<Self>
+    where
+        S: ToString,
+    {
+        if let Ok(metadata) = path.symlink_metadata() {
             let file_type = if metadata.file_type().is_char_device() && metadata.rdev() == 0 {
                 Some(NodeFileType::Whiteout)
             } else {
                 Some(NodeFileType::from(metadata.file_type()))
             };
             if let Some(file_type) = file_type {
-                let replace = file_type == NodeFileType::Directory && Self::dir_is_replace(&path);
+                let replace = file_type == NodeFileType::Directory && Self::dir_is_replace(path);
                 if replace {
                     log::debug!("{} need replace", path.display());
                 }
@@ -130,7 +152,7 @@ impl Node {
                     name: name.to_string(),
                     file_type,
                     children: HashMap::default(),
-                    module_path: Some(path),
+                    module_path: Some(path.to_path_buf()),
                     replace,
                     skip: false,
                 });
@@ -92,7 +92,7 @@ pub fn mount_systemlessly(
 }
 
 #[allow(dead_code)]
-fn mount_partition<S>(partition_name: S, lowerdir: &Vec<String>, mount_source: &str) -> Result<()>
+fn mount_partition<S>(partition_name: S, lowerdir: &[String], mount_source: &str) -> Result<()>
 where
     S: AsRef<str>,
 {
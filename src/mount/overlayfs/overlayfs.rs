@@ -3,30 +3,24 @@
 
 use std::{
     ffi::CString,
-    os::fd::AsFd,
+    fs::{create_dir_all, remove_dir},
+    os::fd::{AsFd, OwnedFd},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result};
 use procfs::process::Process;
 use rustix::{
     fs::CWD,
     mount::{
-        FsMountFlags, FsOpenFlags, MountAttrFlags, MountFlags, MoveMountFlags, fsconfig_create,
-        fsconfig_set_string, fsmount, fsopen, mount, move_mount,
+        FsMountFlags, FsOpenFlags, MountAttrFlags, MountFlags, MoveMountFlags, OpenTreeFlags,
+        UnmountFlags, fsconfig_create, fsconfig_set_string, fsmount, fsopen, mount, mount_move,
+        move_mount, open_tree, unmount,
     },
 };
 
-use crate::{mount::overlayfs::utils::umount_dir, try_umount::send_umountable};
-
-/// Overlay 配置封装，用于在函数间安全传递挂载参数
-struct OverlayOptions<'a> {
-    lower_dirs: &'a [String],
-    lowest: &'a str,
-    upper_dir: Option<PathBuf>,
-    work_dir: Option<PathBuf>,
-    mount_source: &'a str,
-}
+use crate::{defs, try_umount::send_umountable};
 
 /// 核心函数：以原子化特征执行 OverlayFS 挂载
 /// 逻辑：New API (fsopen) -> fsmount -> move_mount => Fallback to mount()
@@ -75,46 +69,109 @@ pub fn mount_overlayfs(
     Ok(())
 }
 
-/// 绑定挂载：使用 open_tree 提供更好的挂载树一致性
-pub fn bind_mount(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
-    let from = from.as_ref();
-    let to = to.as_ref();
+/// 暂存目录序号，全进程递增：嵌套的 mount_overlay 调用时外层的暂存尚未恢复，不能复用编号
+static STASH_SEQ: AtomicUsize = AtomicUsize::new(0);
 
-    use rustix::mount::{OpenTreeFlags, open_tree};
-    
-    // 优先使用 open_tree + move_mount，这允许我们在挂载前克隆挂载树
-    let result = (|| {
-        let tree = open_tree(
-            CWD,
-            from,
-            OpenTreeFlags::OPEN_TREE_CLOEXEC | OpenTreeFlags::OPEN_TREE_CLONE | OpenTreeFlags::AT_RECURSIVE,
-        )?;
-        move_mount(
-            tree.as_fd(),
-            "",
-            CWD,
-            to,
-            MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
-        )
-    })();
+fn next_stash_dir() -> PathBuf {
+    Path::new(defs::RUN_DIR)
+        .join("stock_mounts")
+        .join(STASH_SEQ.fetch_add(1, Ordering::Relaxed).to_string())
+}
 
-    if result.is_err() {
-        log::debug!("open_tree failed, falling back to traditional bind mount");
-        mount(from, to, "", MountFlags::BIND | MountFlags::REC, None)
-            .context("Traditional bind mount failed")?;
+/// 目标下原有子挂载的保存形式
+enum StockMount {
+    /// open_tree 克隆出的分离挂载树，保留原有挂载标志与传播关系
+    Tree(OwnedFd),
+    /// 旧内核回退：递归绑定到暂存目录
+    Stash(PathBuf),
+}
+
+/// 在 Overlay 覆盖目标之前保存子挂载
+fn detach_stock_mount(mount_point: &str) -> Result<StockMount> {
+    match open_tree(
+        CWD,
+        mount_point,
+        OpenTreeFlags::OPEN_TREE_CLOEXEC
+            | OpenTreeFlags::OPEN_TREE_CLONE
+            | OpenTreeFlags::AT_RECURSIVE,
+    ) {
+        Ok(tree) => Ok(StockMount::Tree(tree)),
+        Err(e) => {
+            log::debug!(
+                "open_tree({}) failed: {}, stashing with bind mount",
+                mount_point,
+                e
+            );
+            let stash = next_stash_dir();
+            create_dir_all(&stash)?;
+            mount(
+                mount_point,
+                &stash,
+                "",
+                MountFlags::BIND | MountFlags::REC,
+                None,
+            )
+            .with_context(|| format!("Failed to stash child mount {}", mount_point))?;
+            Ok(StockMount::Stash(stash))
+        }
     }
-    
-    Ok(())
 }
 
-/// 处理嵌套子挂载点
+/// 找出 root 之下的顶层子挂载（更深层的挂载由递归克隆一并带走）
+fn collect_child_mounts(root: &str) -> Result<Vec<String>> {
+    let mounts = Process::myself()?.mountinfo()?;
+    Ok(top_level_mounts(
+        root,
+        mounts.0.into_iter().map(|m| m.mount_point),
+    ))
+}
+
+fn top_level_mounts(root: &str, mount_points: impl Iterator<Item = PathBuf>) -> Vec<String> {
+    let mut children: Vec<PathBuf> = mount_points
+        .filter(|m| m.starts_with(root) && m.as_path() != Path::new(root))
+        .collect();
+    children.sort();
+    children.dedup();
+
+    children
+        .iter()
+        .filter(|c| {
+            !children
+                .iter()
+                .any(|other| other != *c && c.starts_with(other))
+        })
+        .map(|c| c.to_string_lossy().to_string())
+        .collect()
+}
+
+/// 处理嵌套子挂载点：先原样恢复原有子挂载，再按需在其上叠加模块内容
 fn mount_overlay_child(
     mount_point: &str,
     relative: &str,
     module_roots: &[String],
-    stock_root: &str,
+    stock: StockMount,
     mount_source: &str,
 ) -> Result<()> {
+    match stock {
+        StockMount::Tree(tree) => move_mount(
+            tree.as_fd(),
+            "",
+            CWD,
+            mount_point,
+            MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
+        )
+        .with_context(|| format!("Failed to re-attach child mount {}", mount_point))?,
+        StockMount::Stash(stash) => {
+            let res = mount_move(&stash, mount_point)
+                .with_context(|| format!("Failed to restore child mount {}", mount_point));
+            if res.is_err() {
+                let _ = unmount(&stash, UnmountFlags::DETACH);
+            }
+            let _ = remove_dir(&stash);
+            res?
+        }
+    }
+
     // 筛选出确实包含该子路径的模块
     let sub_lowers: Vec<String> = module_roots
         .iter()
@@ -123,45 +180,87 @@ fn mount_overlay_child(
         .collect();
 
     if sub_lowers.is_empty() {
-        // 如果没有任何模块涉及此路径，使用 bind_mount 保持物理透传
-        return bind_mount(stock_root, mount_point);
+        return Ok(());
     }
 
-    // 执行嵌套挂载
-    mount_overlayfs(&sub_lowers, stock_root, None, None, mount_point, mount_source)?;
-    let _ = send_umountable(mount_point);
-    Ok(())
+    // 在恢复的原有挂载之上执行嵌套 Overlay，递归保护更深层的子挂载
+    mount_overlay(mount_point, &sub_lowers, None, None, mount_source)
 }
 
 /// 挂载主入口：执行根挂载并原子化处理子挂载保护
 pub fn mount_overlay(
-    root: &String,
-    module_roots: &Vec<String>,
+    root: &str,
+    module_roots: &[String],
     workdir: Option<PathBuf>,
     upperdir: Option<PathBuf>,
     mount_source: &str,
 ) -> Result<()> {
-    // 扫描并保护子挂载点
-    let mounts = Process::myself()?.mountinfo()?;
-    let mut children: Vec<_> = mounts.0.iter()
-        .filter(|m| m.mount_point.starts_with(root) && m.mount_point.to_string_lossy() != *root)
-        .map(|m| m.mount_point.to_string_lossy().to_string())
-        .collect();
-    children.sort();
+    // Overlay 会遮蔽目标下所有子挂载，先全部保存
+    let mut stock_mounts = Vec::new();
+    for mnt in collect_child_mounts(root)? {
+        match detach_stock_mount(&mnt) {
+            Ok(stock) => stock_mounts.push((mnt, stock)),
+            Err(e) => log::warn!("Child mount {} will be shadowed: {:#}", mnt, e),
+        }
+    }
 
-    mount_overlayfs(module_roots, root, upperdir, workdir, root, mount_source)?;
+    if let Err(e) = mount_overlayfs(module_roots, root, upperdir, workdir, root, mount_source) {
+        for (_, stock) in stock_mounts {
+            if let StockMount::Stash(stash) = stock {
+                let _ = unmount(&stash, UnmountFlags::DETACH);
+                let _ = remove_dir(&stash);
+            }
+        }
+        return Err(e);
+    }
 
-    for mnt in children {
+    for (mnt, stock) in stock_mounts {
         let rel = mnt.replacen(root, "", 1);
-        let sub_lowers: Vec<_> = module_roots.iter()
-            .map(|r| Path::new(r).join(rel.trim_start_matches('/')).to_string_lossy().to_string())
-            .filter(|p| Path::new(p).is_dir())
-            .collect();
-
-        if !sub_lowers.is_empty() {
-            let _ = mount_overlayfs(&sub_lowers, &mnt, None, None, &mnt, mount_source);
+        if let Err(e) = mount_overlay_child(&mnt, &rel, module_roots, stock, mount_source) {
+            log::warn!("Failed to restore child mount {}: {:#}", mnt, e);
         }
     }
     let _ = send_umountable(root);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn nested_child_mounts_get_distinct_stashes() {
+        let mounts = || {
+            [
+                "/",
+                "/vendor",
+                "/vendor/a",
+                "/vendor/a/x",
+                "/vendor/a/y",
+                "/vendor/b",
+                "/vendorx",
+            ]
+            .into_iter()
+            .map(PathBuf::from)
+        };
+
+        // 按 mount_overlay 的顺序：外层先保存全部顶层子挂载，恢复 /vendor/a 时嵌套调用再保存其子挂载
+        let outer = top_level_mounts("/vendor", mounts());
+        assert_eq!(outer, vec!["/vendor/a", "/vendor/b"]);
+        let outer_stashes: Vec<PathBuf> = outer.iter().map(|_| next_stash_dir()).collect();
+
+        let nested = top_level_mounts("/vendor/a", mounts());
+        assert_eq!(nested, vec!["/vendor/a/x", "/vendor/a/y"]);
+        let nested_stashes: Vec<PathBuf> = nested.iter().map(|_| next_stash_dir()).collect();
+
+        // /vendor/b 的暂存此时仍未恢复，不能被嵌套调用覆盖
+        let all: HashSet<&PathBuf> = outer_stashes.iter().chain(&nested_stashes).collect();
+        assert_eq!(all.len(), 4);
+        assert!(
+            all.iter()
+                .all(|p| p.starts_with(Path::new(defs::RUN_DIR).join("stock_mounts")))
+        );
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// 系统属性来源，便于在设备之外用文件模拟
+pub trait PropertySource {
+    fn get(&self, key: &str) -> Option<String>;
+}
+
+/// 通过 getprop 读取设备上的实时属性
+pub struct SystemProperties;
+
+impl PropertySource for SystemProperties {
+    fn get(&self, key: &str) -> Option<String> {
+        let output = Command::new("getprop").arg(key).output().ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        (!value.is_empty()).then_some(value)
+    }
+}
+
+/// 从 build.prop 格式的文件读取属性，每次读取都会重新打开文件
+pub struct PropFile {
+    path: PathBuf,
+}
+
+impl PropFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl PropertySource for PropFile {
+    fn get(&self, key: &str) -> Option<String> {
+        let content = fs::read_to_string(&self.path).ok()?;
+
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .filter(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim().to_string())
+            .next_back()
+    }
+}
+
+/// 未指定文件时使用设备属性
+pub fn source_from(path: Option<&Path>) -> Box<dyn PropertySource> {
+    match path {
+        Some(path) => Box::new(PropFile::new(path)),
+        None => Box::new(SystemProperties),
+    }
+}
@@ -0,0 +1,308 @@
+// Copyright 2026 Hybrid Mount Developers
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{fs, path::Path, sync::OnceLock};
+
+use clap::ValueEnum;
+use regex_lite::Regex;
+use serde::Serialize;
+
+/// 设备上的 file_contexts，按 libselinux 的加载顺序排列（后加载者优先）
+const FILE_CONTEXTS_PATHS: &[&str] = &[
+    "/system/etc/selinux/plat_file_contexts",
+    "/system_ext/etc/selinux/system_ext_file_contexts",
+    "/product/etc/selinux/product_file_contexts",
+    "/vendor/etc/selinux/vendor_file_contexts",
+    "/odm/etc/selinux/odm_file_contexts",
+];
+
+const LEGACY_FILE_CONTEXTS_PATHS: &[&str] = &["/plat_file_contexts", "/vendor_file_contexts"];
+
+static DEVICE_CONTEXTS: OnceLock<FileContexts> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Regular,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Socket,
+    Fifo,
+}
+
+impl FileKind {
+    fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "--" => Some(Self::Regular),
+            "-d" => Some(Self::Directory),
+            "-l" => Some(Self::Symlink),
+            "-c" => Some(Self::CharDevice),
+            "-b" => Some(Self::BlockDevice),
+            "-s" => Some(Self::Socket),
+            "-p" => Some(Self::Fifo),
+            _ => None,
+        }
+    }
+
+    pub fn from_file_type(ft: fs::FileType) -> Self {
+        use std::os::unix::fs::FileTypeExt;
+
+        if ft.is_dir() {
+            Self::Directory
+        } else if ft.is_symlink() {
+            Self::Symlink
+        } else if ft.is_char_device() {
+            Self::CharDevice
+        } else if ft.is_block_device() {
+            Self::BlockDevice
+        } else if ft.is_socket() {
+            Self::Socket
+        } else if ft.is_fifo() {
+            Self::Fifo
+        } else {
+            Self::Regular
+        }
+    }
+
+    pub fn of_path(path: &Path) -> Option<Self> {
+        fs::symlink_metadata(path)
+            .ok()
+            .map(|m| Self::from_file_type(m.file_type()))
+    }
+}
+
+struct Spec {
+    pattern: String,
+    /// 首个正则元字符之前的字面前缀，用于快速过滤
+    stem: String,
+    has_meta: bool,
+    kind: Option<FileKind>,
+    context: Option<String>,
+    regex: OnceLock<Option<Regex>>,
+}
+
+impl Spec {
+    fn matches(&self, path: &str, kind: FileKind) -> bool {
+        if self.kind.is_some_and(|k| k != kind) {
+            return false;
+        }
+
+        if !self.has_meta {
+            return self.pattern == path;
+        }
+
+        if !path.starts_with(&self.stem) {
+            return false;
+        }
+
+        self.regex
+            .get_or_init(|| match Regex::new(&format!("^(?:{})$", self.pattern)) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    log::debug!("Skipping file_contexts spec '{}': {}", self.pattern, e);
+                    None
+                }
+            })
+            .as_ref()
+            .is_some_and(|re| re.is_match(path))
+    }
+}
+
+/// 解析后的 file_contexts 数据库
+#[derive(Default)]
+pub struct FileContexts {
+    specs: Vec<Spec>,
+}
+
+impl FileContexts {
+    pub fn load_device() -> Self {
+        let mut contexts = Self::default();
+
+        let mut loaded = false;
+        for path in FILE_CONTEXTS_PATHS {
+            loaded |= contexts.load_file(Path::new(path));
+        }
+
+        if !loaded {
+            for path in LEGACY_FILE_CONTEXTS_PATHS {
+                contexts.load_file(Path::new(path));
+            }
+        }
+
+        contexts.sort();
+        log::debug!("Loaded {} file_contexts specs", contexts.specs.len());
+        contexts
+    }
+
+    fn load_file(&mut self, path: &Path) -> bool {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                self.parse(&content);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn parse(&mut self, content: &str) {
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (pattern, kind, context) = match fields.as_slice() {
+                [pattern, context] => (*pattern, None, *context),
+                [pattern, flag, context] => match FileKind::from_flag(flag) {
+                    Some(kind) => (*pattern, Some(kind), *context),
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            let stem_end = pattern
+                .find(|c: char| ".^$?*+|[({\\".contains(c))
+                .unwrap_or(pattern.len());
+
+            self.specs.push(Spec {
+                pattern: pattern.to_string(),
+                stem: pattern[..stem_end].to_string(),
+                has_meta: stem_end < pattern.len(),
+                kind,
+                context: (context != "<<none>>").then(|| context.to_string()),
+                regex: OnceLock::new(),
+            });
+        }
+    }
+
+    /// 与 libselinux 一致：不含元字符的精确条目排在最后，保持各自的相对顺序
+    fn sort(&mut self) {
+        let (exact, regex): (Vec<Spec>, Vec<Spec>) = std::mem::take(&mut self.specs)
+            .into_iter()
+            .partition(|s| !s.has_meta);
+        self.specs = regex;
+        self.specs.extend(exact);
+    }
+
+    /// 查找路径的标签：从后向前匹配，最后定义的条目优先
+    pub fn lookup(&self, path: &Path, kind: FileKind) -> Option<String> {
+        let path_str = path.to_string_lossy();
+        self.specs
+            .iter()
+            .rev()
+            .find(|spec| spec.matches(&path_str, kind))
+            .and_then(|spec| spec.context.clone())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+}
+
+/// 基于设备 file_contexts 计算路径应有的 SELinux 标签
+pub fn lookup(path: &Path, kind: FileKind) -> Option<String> {
+    let contexts = DEVICE_CONTEXTS.get_or_init(FileContexts::load_device);
+    if contexts.is_empty() {
+        return None;
+    }
+    contexts.lookup(path, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contexts(content: &str) -> FileContexts {
+        let mut contexts = FileContexts::default();
+        contexts.parse(content);
+        contexts.sort();
+        contexts
+    }
+
+    fn label(contexts: &FileContexts, path: &str, kind: FileKind) -> Option<String> {
+        contexts.lookup(Path::new(path), kind)
+    }
+
+    const PLAT: &str = r#"
+# System
+/system(/.*)?                   u:object_r:system_file:s0
+/system/bin/sh          --      u:object_r:shell_exec:s0
+/system/bin/toybox      --      u:object_r:toolbox_exec:s0
+/system/lib(64)?/libc\.so       u:object_r:system_lib_file:s0
+/system/etc/selinux(/.*)?       u:object_r:sepolicy_file:s0
+"#;
+
+    #[test]
+    fn later_regex_overrides_earlier_regex() {
+        let contexts = contexts(PLAT);
+
+        assert_eq!(
+            label(
+                &contexts,
+                "/system/etc/selinux/plat_sepolicy.cil",
+                FileKind::Regular
+            )
+            .as_deref(),
+            Some("u:object_r:sepolicy_file:s0")
+        );
+        assert_eq!(
+            label(&contexts, "/system/lib64/libc.so", FileKind::Regular).as_deref(),
+            Some("u:object_r:system_lib_file:s0")
+        );
+        assert_eq!(
+            label(&contexts, "/system/app/Foo/Foo.apk", FileKind::Regular).as_deref(),
+            Some("u:object_r:system_file:s0")
+        );
+    }
+
+    #[test]
+    fn exact_specs_win_over_regex_defined_later() {
+        // vendor_file_contexts 在 plat 之后加载，但精确条目仍排在所有正则之后
+        let contexts = contexts(&format!(
+            "{}\n/system/bin(/.*)?  u:object_r:vendor_override_file:s0\n",
+            PLAT
+        ));
+
+        assert_eq!(
+            label(&contexts, "/system/bin/sh", FileKind::Regular).as_deref(),
+            Some("u:object_r:shell_exec:s0")
+        );
+        assert_eq!(
+            label(&contexts, "/system/bin/ls", FileKind::Regular).as_deref(),
+            Some("u:object_r:vendor_override_file:s0")
+        );
+    }
+
+    #[test]
+    fn file_kind_restricts_matches() {
+        let contexts = contexts(PLAT);
+
+        assert_eq!(
+            label(&contexts, "/system/bin/sh", FileKind::Symlink).as_deref(),
+            Some("u:object_r:system_file:s0")
+        );
+    }
+
+    #[test]
+    fn none_context_and_unmatched_paths() {
+        let contexts =
+            contexts("/data(/.*)?  u:object_r:system_data_file:s0\n/data/cache  -d  <<none>>\n");
+
+        assert_eq!(label(&contexts, "/data/cache", FileKind::Directory), None);
+        assert_eq!(
+            label(&contexts, "/data/cache", FileKind::Regular).as_deref(),
+            Some("u:object_r:system_data_file:s0")
+        );
+        assert_eq!(label(&contexts, "/vendor/bin/sh", FileKind::Regular), None);
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let contexts = contexts("/system  -x  u:object_r:system_file:s0\n/only_pattern\n");
+
+        assert!(contexts.is_empty());
+    }
+}
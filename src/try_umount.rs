@@ -12,7 +12,8 @@ use ksu::TryUmount;
 
 pub static TMPFS: OnceLock<String> = OnceLock::new();
 pub static LIST: LazyLock<Mutex<TryUmount>> = LazyLock::new(|| Mutex::new(TryUmount::new()));
-static HISTORY: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+/// 已登记、尚未被挂载事务取走的卸载目标
+static PENDING: LazyLock<Mutex<Vec<String>>> = LazyLock::new(|| Mutex::new(Vec::new()));
 
 pub fn send_umountable<P>(target: P) -> Result<()>
 where
@@ -22,24 +23,26 @@ where
         return Ok(());
     }
 
-    let path_str = target.as_ref().to_string_lossy().to_string();
-    let mut history = HISTORY
+    PENDING
         .lock()
-        .map_err(|_| anyhow::anyhow!("Failed to lock history mutex"))?;
-
-    if history.contains(&path_str) {
-        log::debug!("Ignored duplicate umount request: {}", path_str);
-        return Ok(());
-    }
-
-    history.insert(path_str);
-    LIST.lock()
-        .map_err(|_| anyhow::anyhow!("Failed to lock umount list"))?
-        .add(target);
+        .map_err(|_| anyhow::anyhow!("Failed to lock pending umount list"))?
+        .push(target.as_ref().to_string_lossy().to_string());
     Ok(())
 }
 
-pub fn commit() -> Result<()> {
+/// 取走目前登记的卸载目标，由挂载事务保管，回滚时随挂载一同丢弃
+pub fn take_pending() -> Vec<String> {
+    PENDING
+        .lock()
+        .map(|mut pending| std::mem::take(&mut *pending))
+        .unwrap_or_default()
+}
+
+/// 将卸载目标交给 KSU，应只在所有挂载阶段成功后调用
+pub fn commit<I>(targets: I) -> Result<()>
+where
+    I: IntoIterator<Item = String>,
+{
     if !crate::utils::KSU.load(std::sync::atomic::Ordering::Relaxed) {
         return Ok(());
     }
@@ -47,6 +50,15 @@ pub fn commit() -> Result<()> {
         .lock()
         .map_err(|_| anyhow::anyhow!("Failed to lock umount list"))?;
 
+    let mut seen = HashSet::new();
+    for target in targets {
+        if !seen.insert(target.clone()) {
+            log::debug!("Ignored duplicate umount request: {}", target);
+            continue;
+        }
+        list.add(target);
+    }
+
     // Attempt 1: Normal umount (0)
     list.flags(0);
     if let Err(e0) = list.umount() {
@@ -7,11 +7,11 @@ use std::{
     io::Write,
     os::unix::{
         ffi::OsStrExt,
-        fs::{FileTypeExt, MetadataExt, PermissionsExt, symlink},
+        fs::{FileExt, FileTypeExt, MetadataExt, PermissionsExt, symlink},
     },
-    path::{Path, PathBuf},
+    path::Path,
     process::{Command, Stdio},
-    sync::{OnceLock, atomic::AtomicBool},
+    sync::{Mutex, OnceLock, atomic::AtomicBool},
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -21,11 +21,14 @@ use extattr::{Flags as XattrFlags, lgetxattr, llistxattr, lsetxattr};
 use procfs::process::Process;
 use regex_lite::Regex;
 use rustix::{
-    fs::ioctl_ficlone,
+    fs::{AtFlags, CWD, SeekFrom, Timespec, Timestamps, ioctl_ficlone, seek, utimensat},
+    io::Errno,
     mount::{MountFlags, mount},
 };
 use walkdir::WalkDir;
 
+use crate::selinux;
+
 const SELINUX_XATTR: &str = "security.selinux";
 const OVERLAY_OPAQUE_XATTR: &str = "trusted.overlay.opaque";
 const CONTEXT_SYSTEM: &str = "u:object_r:system_file:s0";
@@ -41,6 +44,9 @@ const XATTR_TEST_FILE: &str = ".xattr_test";
 
 static MODULE_ID_REGEX: OnceLock<Regex> = OnceLock::new();
 
+/// 日志系统初始化前产生的日志（例如加载配置时）
+static EARLY_LOG: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+
 pub fn check_ksu() {
     let status = ksu::version().is_some_and(|v| {
         log::info!("KernelSU Version: {v}");
@@ -88,9 +94,33 @@ pub fn init_logging(verbose: bool) -> Result<()> {
         });
         builder.filter_level(level).init();
     }
+
+    flush_early_log();
+
     Ok(())
 }
 
+/// 日志尚未初始化时先缓存，初始化后由 init_logging 输出
+pub fn log_early(level: log::Level, message: String) {
+    if log::max_level() == log::LevelFilter::Off {
+        if let Ok(mut pending) = EARLY_LOG.lock() {
+            pending.push((level, message));
+        }
+    } else {
+        log::log!(level, "{}", message);
+    }
+}
+
+fn flush_early_log() {
+    let Ok(mut pending) = EARLY_LOG.lock() else {
+        return;
+    };
+
+    for (level, message) in pending.drain(..) {
+        log::log!(level, "{}", message);
+    }
+}
+
 /// 原子性写入文件，包含清理守卫
 pub fn atomic_write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, content: C) -> Result<()> {
     let path = path.as_ref();
@@ -138,30 +168,100 @@ pub fn check_zygisksu_enforce_status() -> bool {
         .unwrap_or(false)
 }
 
-fn copy_extended_attributes(src: &Path, dst: &Path) -> Result<()> {
-    #[cfg(any(target_os = "linux", target_os = "android"))]
-    {
-        // 1. 同步 SELinux 上下文
-        if let Ok(mut ctx) = lgetfilecon(src) {
-            if ctx.contains(CONTEXT_ROOTFS) {
-                ctx = CONTEXT_SYSTEM.to_string();
-            }
-            lsetfilecon(dst, &ctx).warn_on_err();
+/// 扩展属性过滤规则：deny 优先于 allow，名称支持 * 与 ? 通配
+#[derive(Debug, Clone)]
+pub struct XattrFilter {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl Default for XattrFilter {
+    fn default() -> Self {
+        Self {
+            allow: vec!["*".to_string()],
+            deny: Vec::new(),
         }
+    }
+}
+
+impl XattrFilter {
+    pub fn allows(&self, name: &str) -> bool {
+        !self.deny.iter().any(|p| wildcard_match(p, name))
+            && self.allow.iter().any(|p| wildcard_match(p, name))
+    }
+}
 
-        // 2. 同步 OverlayFS 不透明属性
-        if let Ok(opaque) = lgetxattr(src, OVERLAY_OPAQUE_XATTR) {
-            lsetxattr(dst, OVERLAY_OPAQUE_XATTR, &opaque, XattrFlags::empty())
-                .context("Failed to set opaque xattr")?;
+/// 同步条目时的选项
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// 按系统路径重新计算 SELinux 标签
+    pub repair: bool,
+    pub xattrs: XattrFilter,
+}
+
+/// 单段通配符匹配，* 匹配任意长度，? 匹配单个字符
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // 经典的回溯式通配符匹配，只需记住最近一个 * 的位置
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
         }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 复制过滤规则允许的全部扩展属性
+/// 须在 chown 之后调用，否则 security.capability 会被内核清除
+fn copy_extended_attributes(src: &Path, dst: &Path, filter: &XattrFilter) -> Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let Ok(names) = llistxattr(src) else {
+            return Ok(());
+        };
+
+        for name in names {
+            let name_str = String::from_utf8_lossy(name.as_bytes()).to_string();
+            if !filter.allows(&name_str) {
+                continue;
+            }
+            let Ok(value) = lgetxattr(src, &name) else {
+                continue;
+            };
 
-        // 3. 同步其他受信任的 Overlay 属性
-        if let Ok(xattrs) = llistxattr(src) {
-            for xattr_name in xattrs {
-                let name_str = String::from_utf8_lossy(xattr_name.as_bytes());
-                if name_str.starts_with("trusted.overlay.") && name_str != OVERLAY_OPAQUE_XATTR {
-                    if let Ok(val) = lgetxattr(src, &xattr_name) {
-                        lsetxattr(dst, &xattr_name, &val, XattrFlags::empty()).ok();
+            match name_str.as_str() {
+                // rootfs 标签在模块中无意义，改为 system_file
+                SELINUX_XATTR => {
+                    let ctx = String::from_utf8_lossy(&value).trim_matches('\0').to_string();
+                    if ctx.contains(CONTEXT_ROOTFS) {
+                        lsetfilecon(dst, CONTEXT_SYSTEM).warn_on_err();
+                    } else {
+                        lsetfilecon(dst, &ctx).warn_on_err();
+                    }
+                }
+                OVERLAY_OPAQUE_XATTR => {
+                    lsetxattr(dst, &name, &value, XattrFlags::empty())
+                        .context("Failed to set opaque xattr")?;
+                }
+                _ => {
+                    if let Err(e) = lsetxattr(dst, &name, &value, XattrFlags::empty()) {
+                        log::debug!("Failed to copy xattr {} to {}: {}", name_str, dst.display(), e);
                     }
                 }
             }
@@ -170,6 +270,39 @@ fn copy_extended_attributes(src: &Path, dst: &Path) -> Result<()> {
     Ok(())
 }
 
+/// 还原属主与权限位，先 chown 再 chmod，避免 chown 清除 setuid/setgid
+fn copy_ownership(metadata: &fs::Metadata, dst: &Path) -> Result<()> {
+    std::os::unix::fs::lchown(dst, Some(metadata.uid()), Some(metadata.gid()))
+        .with_context(|| format!("Failed to chown {}", dst.display()))?;
+
+    if !metadata.file_type().is_symlink() {
+        fs::set_permissions(dst, metadata.permissions())?;
+    }
+    Ok(())
+}
+
+/// 还原访问与修改时间，不跟随软链接
+pub fn copy_timestamps(src: &Path, dst: &Path) -> Result<()> {
+    set_timestamps(dst, &fs::symlink_metadata(src)?)
+}
+
+/// 按给定元数据设置访问与修改时间，不跟随软链接
+pub fn set_timestamps(dst: &Path, metadata: &fs::Metadata) -> Result<()> {
+    let times = Timestamps {
+        last_access: Timespec {
+            tv_sec: metadata.atime(),
+            tv_nsec: metadata.atime_nsec() as _,
+        },
+        last_modification: Timespec {
+            tv_sec: metadata.mtime(),
+            tv_nsec: metadata.mtime_nsec() as _,
+        },
+    };
+
+    utimensat(CWD, dst, &times, AtFlags::SYMLINK_NOFOLLOW)
+        .with_context(|| format!("Failed to set timestamps on {}", dst.display()))
+}
+
 pub fn set_overlay_opaque<P: AsRef<Path>>(path: P) -> Result<()> {
     #[cfg(any(target_os = "linux", target_os = "android"))]
     {
@@ -316,8 +449,21 @@ pub fn is_mounted<P: AsRef<Path>>(path: P) -> bool {
 }
 
 pub fn mount_tmpfs(target: &Path, source: &str) -> Result<()> {
+    mount_tmpfs_with_options(target, source, "mode=0755")
+}
+
+/// 挂载限制容量与 inode 数量的 tmpfs
+pub fn mount_tmpfs_sized(target: &Path, source: &str, size: u64, nr_inodes: u64) -> Result<()> {
+    mount_tmpfs_with_options(
+        target,
+        source,
+        &format!("mode=0755,size={},nr_inodes={}", size, nr_inodes),
+    )
+}
+
+fn mount_tmpfs_with_options(target: &Path, source: &str, options: &str) -> Result<()> {
     ensure_dir_exists(target)?;
-    let data = CString::new("mode=0755")?;
+    let data = CString::new(options)?;
     mount(
         source,
         target,
@@ -329,37 +475,72 @@ pub fn mount_tmpfs(target: &Path, source: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn repair_image(image_path: &Path) -> Result<()> {
-    log::info!("Running e2fsck on {}", image_path.display());
-    let status = Command::new("e2fsck")
-        .args(["-y", "-f"])
-        .arg(image_path)
-        .status()
-        .context("Failed to execute e2fsck")?;
+/// 让已存在的 dest 与 src 共享数据块，dest 保留自身的 inode 与属主
+/// 内核会像普通写入一样更新 dest 的修改时间并清除其 setuid 位与文件能力
+pub fn reflink_into(src: &Path, dest: &Path) -> Result<()> {
+    let src_file = File::open(src)?;
+    let dest_file = OpenOptions::new().write(true).open(dest)?;
 
-    if let Some(code) = status.code()
-        && code > 2
-    {
-        bail!("e2fsck failed with exit code: {}", code);
-    }
-    Ok(())
+    ioctl_ficlone(&dest_file, &src_file)
+        .with_context(|| format!("Failed to reflink {} -> {}", dest.display(), src.display()))
 }
 
 pub fn reflink_or_copy(src: &Path, dest: &Path) -> Result<u64> {
     let src_file = File::open(src)?;
     let dest_file = File::create(dest)?;
+    let metadata = src_file.metadata()?;
 
     if ioctl_ficlone(&dest_file, &src_file).is_ok() {
-        let metadata = src_file.metadata()?;
         let len = metadata.len();
         dest_file.set_permissions(metadata.permissions())?;
         return Ok(len);
     }
+
+    // 已分配的块少于文件长度说明存在空洞，逐段复制数据区以保留稀疏结构
+    if metadata.blocks() * 512 < metadata.len() {
+        let copied = copy_sparse(&src_file, &dest_file, metadata.len())?;
+        dest_file.set_permissions(metadata.permissions())?;
+        return Ok(copied);
+    }
+
     drop(dest_file);
     drop(src_file);
     fs::copy(src, dest).map_err(|e| e.into())
 }
 
+/// 借助 SEEK_DATA/SEEK_HOLE 只复制数据区，返回复制的字节数
+fn copy_sparse(src: &File, dst: &File, len: u64) -> Result<u64> {
+    let mut buf = vec![0u8; 128 * 1024];
+    let mut offset = 0;
+    let mut copied = 0;
+
+    while offset < len {
+        let data = match seek(src, SeekFrom::Data(offset)) {
+            Ok(pos) => pos,
+            // 之后只剩空洞
+            Err(Errno::NXIO) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let hole = seek(src, SeekFrom::Hole(data))?.min(len);
+
+        let mut pos = data;
+        while pos < hole {
+            let want = ((hole - pos) as usize).min(buf.len());
+            let n = src.read_at(&mut buf[..want], pos)?;
+            if n == 0 {
+                break;
+            }
+            dst.write_all_at(&buf[..n], pos)?;
+            pos += n as u64;
+            copied += n as u64;
+        }
+        offset = hole;
+    }
+
+    dst.set_len(len)?;
+    Ok(copied)
+}
+
 fn make_device_node(path: &Path, mode: u32, rdev: u64) -> Result<()> {
     let c_path = CString::new(path.as_os_str().as_encoded_bytes())?;
     let dev = rdev as libc::dev_t;
@@ -406,10 +587,12 @@ fn apply_system_context(current: &Path, relative: &Path) -> Result<()> {
         return Ok(());
     }
 
+    // system_file 是安装器的默认标签，仍需按 file_contexts 细化
     let current_ctx = lgetfilecon(current).ok();
     if let Some(ctx) = &current_ctx
         && !ctx.is_empty()
         && ctx != CONTEXT_ROOTFS
+        && ctx != CONTEXT_SYSTEM
         && ctx != "u:object_r:unlabeled:s0"
     {
         return Ok(());
@@ -426,6 +609,12 @@ fn apply_system_context(current: &Path, relative: &Path) -> Result<()> {
             let _ = lsetfilecon(current, target_ctx);
             return Ok(());
         }
+    } else if let Some(kind) = selinux::FileKind::of_path(current)
+        && let Some(ctx) = selinux::lookup(&system_path, kind)
+    {
+        // 新路径没有原厂对应文件，按设备 file_contexts 计算标签
+        let _ = lsetfilecon(current, &ctx);
+        return Ok(());
     } else if let Some(parent) = system_path.parent()
         && parent.exists()
         && let Ok(parent_ctx) = lgetfilecon(parent)
@@ -453,59 +642,45 @@ impl<T, E: std::fmt::Display> WarnErr for Result<T, E> {
     }
 }
 
-fn iterative_sync(src: &Path, dst: &Path, repair: bool) -> Result<()> {
-    // 显式指定 Vec 的元组类型
-    let mut stack: Vec<(PathBuf, PathBuf, PathBuf)> = vec![(src.to_path_buf(), dst.to_path_buf(), PathBuf::new())];
+/// 同步单个条目：目录只建立自身（不递归），其余类型整体替换
+/// 还原属主、权限、扩展属性与时间戳；目录的时间戳会被写入子条目改变，由调用方最后处理
+/// 返回复制的字节数，供增量同步统计
+pub fn sync_entry(src: &Path, dst: &Path, relative: &Path, opts: &SyncOptions) -> Result<u64> {
+    let metadata = fs::symlink_metadata(src)?;
+    let ft = metadata.file_type();
 
-    while let Some((curr_src, curr_dst, rel_path)) = stack.pop() {
-        if !curr_dst.exists() {
-            if curr_src.is_dir() {
-                create_dir_all(&curr_dst)?;
-            }
-            if let Ok(src_meta) = curr_src.metadata() {
-                let _ = fs::set_permissions(&curr_dst, src_meta.permissions());
-            }
+    let mut copied = 0;
+    if ft.is_dir() {
+        create_dir_all(dst)?;
+    } else {
+        if fs::symlink_metadata(dst).is_ok() { remove_file(dst)?; }
 
-            if repair {
-                let _ = apply_system_context(&curr_dst, &rel_path);
-            } else {
-                let _ = copy_extended_attributes(&curr_src, &curr_dst);
-            }
+        if ft.is_symlink() {
+            symlink(fs::read_link(src)?, dst)?;
+        } else if ft.is_char_device() || ft.is_block_device() || ft.is_fifo() {
+            make_device_node(dst, metadata.permissions().mode(), metadata.rdev())?;
+        } else {
+            copied = reflink_or_copy(src, dst)?;
         }
+    }
 
-        if curr_src.is_dir() {
-            for entry in fs::read_dir(&curr_src)? {
-                let entry = entry?;
-                let s = entry.path();
-                let name = entry.file_name();
-                let d = curr_dst.join(&name);
-                let next_rel = rel_path.join(&name);
-                
-                let metadata = entry.metadata()?;
-                let ft = metadata.file_type();
-
-                // 处理不同类型的文件
-                if ft.is_dir() {
-                    stack.push((s, d, next_rel));
-                } else {
-                    if ft.is_symlink() {
-                        if d.exists() { remove_file(&d)?; }
-                        symlink(fs::read_link(&s)?, &d)?;
-                    } else if ft.is_char_device() || ft.is_block_device() || ft.is_fifo() {
-                        if d.exists() { remove_file(&d)?; }
-                        make_device_node(&d, metadata.permissions().mode(), metadata.rdev())?;
-                    } else {
-                        reflink_or_copy(&s, &d)?;
-                    }
-                    
-                    // 同步属性
-                    let _ = copy_extended_attributes(&s, &d);
-                    if repair { let _ = apply_system_context(&d, &next_rel); }
-                }
-            }
-        }
+    // 同步属性
+    copy_ownership(&metadata, dst).warn_on_err();
+    let _ = copy_extended_attributes(src, dst, &opts.xattrs);
+    if opts.repair { let _ = apply_system_context(dst, relative); }
+
+    if !ft.is_dir() {
+        copy_timestamps(src, dst).warn_on_err();
     }
-    Ok(())
+
+    Ok(copied)
+}
+
+/// 建立指向已同步副本的硬链接，还原源端的硬链接关系
+pub fn link_entry(existing: &Path, dst: &Path) -> Result<()> {
+    if fs::symlink_metadata(dst).is_ok() { remove_file(dst)?; }
+    fs::hard_link(existing, dst)
+        .with_context(|| format!("Failed to link {} -> {}", dst.display(), existing.display()))
 }
 
 pub fn detect_all_partitions() -> Result<Vec<String>> {
@@ -546,68 +721,6 @@ pub fn detect_all_partitions() -> Result<Vec<String>> {
     Ok(partitions)
 }
 
-fn native_cp_r(src: &Path, dst: &Path, relative: &Path, repair: bool) -> Result<()> {
-    if !dst.exists() {
-        if src.is_dir() {
-            create_dir_all(dst)?;
-        }
-        if let Ok(src_meta) = src.metadata() {
-            let _ = fs::set_permissions(dst, src_meta.permissions());
-        }
-
-        if repair && relative.as_os_str().is_empty() {
-            let _ = apply_system_context(dst, relative);
-        } else if !repair {
-            let _ = copy_extended_attributes(src, dst);
-        }
-    }
-
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let file_name = entry.file_name();
-        let dst_path = dst.join(&file_name);
-        let next_relative = relative.join(&file_name);
-
-        let metadata = entry.metadata()?;
-        let ft = metadata.file_type();
-
-        if ft.is_dir() {
-            native_cp_r(&src_path, &dst_path, &next_relative, repair)?;
-        } else if ft.is_symlink() {
-            if dst_path.exists() {
-                remove_file(&dst_path)?;
-            }
-            let link_target = fs::read_link(&src_path)?;
-            symlink(&link_target, &dst_path)?;
-        } else if ft.is_char_device() || ft.is_block_device() || ft.is_fifo() {
-            if dst_path.exists() {
-                remove_file(&dst_path)?;
-            }
-            let mode = metadata.permissions().mode();
-            let rdev = metadata.rdev();
-            make_device_node(&dst_path, mode, rdev)?;
-        } else {
-            reflink_or_copy(&src_path, &dst_path)?;
-        }
-
-        let _ = copy_extended_attributes(&src_path, &dst_path);
-
-        if repair {
-            let _ = apply_system_context(&dst_path, &next_relative);
-        }
-    }
-    Ok(())
-}
-
-pub fn sync_dir(src: &Path, dst: &Path, repair_context: bool) -> Result<()> {
-    if !src.exists() { return Ok(()); }
-    ensure_dir_exists(dst)?;
-    iterative_sync(src, dst, repair_context).with_context(|| {
-        format!("Failed to sync {} to {}", src.display(), dst.display())
-    })
-}
-
 #[allow(dead_code)]
 pub fn cleanup_temp_dir(temp_dir: &Path) {
     if let Err(e) = remove_dir_all(temp_dir) {
@@ -711,6 +824,15 @@ pub fn extract_module_id(path: &Path) -> Option<String> {
         .map(|s| s.to_string_lossy().to_string())
 }
 
+/// 返回路径所属模块的 ID 及其在模块内的相对路径
+pub fn split_module_path(path: &Path) -> Option<(String, String)> {
+    let root = path.ancestors().find(|p| p.join("module.prop").exists())?;
+    let id = root.file_name()?.to_string_lossy().to_string();
+    let relative = path.strip_prefix(root).ok()?.to_string_lossy().to_string();
+
+    (!relative.is_empty()).then_some((id, relative))
+}
+
 pub fn prune_empty_dirs<P: AsRef<Path>>(root: P) -> Result<()> {
     let root = root.as_ref();
     if !root.exists() {
@@ -734,3 +856,103 @@ pub fn prune_empty_dirs<P: AsRef<Path>>(root: P) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+pub mod test_utils {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    /// 测试用临时目录，离开作用域时删除，断言失败时也不会残留
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "hybrid-{}-{}-{}",
+                name,
+                std::process::id(),
+                NEXT_ID.fetch_add(1, Ordering::Relaxed)
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_matches_literals_and_single_chars() {
+        assert!(wildcard_match("security.selinux", "security.selinux"));
+        assert!(!wildcard_match("security.selinux", "security.selinu"));
+        assert!(wildcard_match("user.?", "user.a"));
+        assert!(!wildcard_match("user.?", "user."));
+        assert!(!wildcard_match("user.?", "user.ab"));
+        assert!(wildcard_match("", ""));
+        assert!(!wildcard_match("", "a"));
+    }
+
+    #[test]
+    fn wildcard_star_backtracks() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*", "trusted.overlay.opaque"));
+        assert!(wildcard_match("trusted.*", "trusted.overlay.opaque"));
+        assert!(!wildcard_match("trusted.*", "security.selinux"));
+        assert!(wildcard_match("*.overlay.*", "trusted.overlay.opaque"));
+        // 首次命中 "a" 后必须回退重试
+        assert!(wildcard_match("*ab", "aab"));
+        assert!(wildcard_match("a*b*c", "axbxbxc"));
+        assert!(!wildcard_match("a*b*c", "axbxbx"));
+        assert!(wildcard_match("user.**", "user."));
+    }
+
+    #[test]
+    fn xattr_filter_default_allows_everything() {
+        let filter = XattrFilter::default();
+
+        assert!(filter.allows("security.selinux"));
+        assert!(filter.allows("security.capability"));
+        assert!(filter.allows("trusted.overlay.opaque"));
+    }
+
+    #[test]
+    fn xattr_filter_deny_wins_over_allow() {
+        let filter = XattrFilter {
+            allow: vec!["security.*".to_string(), "user.keep".to_string()],
+            deny: vec!["security.capability".to_string(), "user.*".to_string()],
+        };
+
+        assert!(filter.allows("security.selinux"));
+        assert!(!filter.allows("security.capability"));
+        assert!(!filter.allows("user.keep"));
+        assert!(!filter.allows("trusted.overlay.opaque"));
+    }
+
+    #[test]
+    fn xattr_filter_empty_allow_list_allows_nothing() {
+        let filter = XattrFilter {
+            allow: Vec::new(),
+            deny: Vec::new(),
+        };
+
+        assert!(!filter.allows("security.selinux"));
+    }
+}
@@ -15,6 +15,7 @@ import type {
   ConflictEntry,
   DiagnosticIssue,
   Silo,
+  RescueReport,
 } from "./types";
 
 const delay = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
@@ -188,11 +189,51 @@ export const MockAPI = {
     await delay(500);
     console.log(`[Mock] Restored silo: ${siloId}`);
   },
+  async getRescueReport(): Promise<RescueReport> {
+    await delay(300);
+    return {
+      session: null,
+      journal: [
+        {
+          timestamp: 1715007200,
+          step: 0,
+          event: "started",
+          modules: ["magisk_module_1", "magisk_module_2"],
+          message: "Bisecting modules changed since the last good boot",
+        },
+        {
+          timestamp: 1715007200,
+          step: 1,
+          event: "disabled",
+          modules: ["magisk_module_1"],
+          message: "Disabled 1 of 2 suspects for the next boot",
+        },
+        {
+          timestamp: 1715007300,
+          step: 1,
+          event: "boot_failed",
+          modules: ["magisk_module_2"],
+          message: "Boot failed, culprit is among the modules left enabled",
+        },
+        {
+          timestamp: 1715007300,
+          step: 1,
+          event: "verdict",
+          modules: ["magisk_module_2"],
+          message:
+            "Module 'magisk_module_2' caused the bootloop and has been disabled",
+        },
+      ],
+    };
+  },
 
   async setWinnowingRule(path: string, moduleId: string): Promise<void> {
     await delay(300);
     console.log(`[Mock] Winnow rule set: ${path} -> ${moduleId}`);
   },
+  subscribeStatus(): () => void {
+    return () => {};
+  },
   openLink(url: string): void {
     console.log("[Mock] Opening link:", url);
     window.open(url, "_blank");
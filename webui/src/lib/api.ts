@@ -16,6 +16,9 @@ import type {
   DiagnosticIssue,
   Silo,
   ModuleRules,
+  RescueReport,
+  RuntimeState,
+  StatusUpdate,
 } from "./types";
 
 interface KsuExecResult {
@@ -24,15 +27,23 @@ interface KsuExecResult {
   stderr: string;
 }
 
+interface KsuChildProcess {
+  stdout: { on: (event: "data", listener: (data: string) => void) => void };
+  on: (event: "exit" | "error", listener: (arg: unknown) => void) => void;
+}
+
 interface KsuModule {
   exec: (cmd: string, options?: unknown) => Promise<KsuExecResult>;
+  spawn: (cmd: string, args?: string[], options?: unknown) => KsuChildProcess;
 }
 
 let ksuExec: KsuModule["exec"] | null = null;
+let ksuSpawn: KsuModule["spawn"] | null = null;
 
 try {
   const ksu = await import("kernelsu").catch(() => null);
   ksuExec = ksu ? ksu.exec : null;
+  ksuSpawn = ksu ? ksu.spawn : null;
 } catch {
   console.warn("KernelSU module not found, defaulting to Mock/Fallback.");
 }
@@ -67,6 +78,169 @@ function stringToHex(str: string): string {
   return hex;
 }
 
+function rpcCommand(method: string, params?: unknown): string {
+  const request = JSON.stringify({ jsonrpc: "2.0", id: 1, method, params });
+  return `${PATHS.BINARY} rpc <<'__META_HYBRID_RPC__'\n${request}\n__META_HYBRID_RPC__`;
+}
+
+/**
+ * Sends one JSON-RPC request to the resident daemon through `meta-hybrid rpc`.
+ * The request travels over stdin and the daemon socket instead of argv.
+ * Resolves to `undefined` when the daemon is not running, so callers can fall
+ * back to the one-shot subcommand; errors reported by the daemon are thrown.
+ */
+async function rpc<T>(
+  method: string,
+  params?: unknown,
+): Promise<T | undefined> {
+  if (!ksuExec) return undefined;
+  const cmd = rpcCommand(method, params);
+  let response: { result?: T; error?: { message: string } };
+  try {
+    const { errno, stdout } = await ksuExec(cmd);
+    const line = stdout.trim().split("\n").pop();
+    if (errno !== 0 || !line) return undefined;
+    response = JSON.parse(line);
+  } catch {
+    return undefined;
+  }
+  if (response.error) throw new Error(response.error.message);
+  return response.result;
+}
+
+/**
+ * Reads through the daemon when it is running, otherwise runs the one-shot
+ * subcommand and parses its JSON output. Resolves to `undefined` on failure.
+ */
+async function query<T>(
+  method: string,
+  fallback: string,
+  params?: unknown,
+): Promise<T | undefined> {
+  if (!ksuExec) return undefined;
+  try {
+    const result = await rpc<T>(method, params);
+    if (result !== undefined) return result;
+  } catch {
+    return undefined;
+  }
+  try {
+    const { errno, stdout } = await ksuExec(fallback);
+    if (errno === 0 && stdout) return JSON.parse(stdout);
+  } catch {
+    // ignore
+  }
+  return undefined;
+}
+
+/** Runs a write through the daemon, or the one-shot subcommand without it. */
+async function command(
+  method: string,
+  params: unknown,
+  fallback: string,
+): Promise<void> {
+  if (!ksuExec) return;
+  if ((await rpc<unknown>(method, params)) !== undefined) return;
+  const { errno, stderr } = await ksuExec(fallback);
+  if (errno !== 0) throw new Error(stderr);
+}
+
+const STATE_FILE =
+  (PATHS as Record<string, string>).DAEMON_STATE ||
+  "/data/adb/meta-hybrid/run/daemon_state.json";
+
+async function loadRuntimeState(): Promise<RuntimeState | undefined> {
+  return query<RuntimeState>("status", `cat "${STATE_FILE}"`);
+}
+
+function storageFromState(state: RuntimeState): StorageStatus {
+  return {
+    type: (state.storage_mode as StorageStatus["type"]) || "unknown",
+    percent: `${state.storage_percent ?? 0}%`,
+    size: formatBytes(state.storage_total ?? 0),
+    used: formatBytes(state.storage_used ?? 0),
+  };
+}
+
+function mountsFromState(state: RuntimeState): StatusUpdate["mounts"] {
+  return {
+    mountBase: state.mount_point || "Unknown",
+    activeMounts: state.active_mounts || [],
+    zygisksuEnforce:
+      state.zygisksu_enforce === undefined
+        ? undefined
+        : state.zygisksu_enforce
+          ? "1"
+          : "0",
+  };
+}
+
+/** Delay before reopening a status stream, e.g. while the daemon starts. */
+const SUBSCRIBE_RETRY_MS = 10000;
+
+/**
+ * Keeps one `meta-hybrid rpc` process open on `status.subscribe` and forwards
+ * the daemon's `status.changed` and `config.changed` pushes. The stream is
+ * reopened after it ends; the returned function stops listening.
+ */
+function subscribeStatus(
+  onStatus: (update: StatusUpdate) => void,
+  onConfig: (config: AppConfig) => void,
+): () => void {
+  let stopped = false;
+
+  const handle = (line: string) => {
+    let message: { method?: string; params?: unknown; result?: unknown };
+    try {
+      message = JSON.parse(line);
+    } catch {
+      return;
+    }
+    // The subscribe response carries the current state; pushes carry a method
+    const state =
+      message.method === "status.changed"
+        ? message.params
+        : message.method === undefined
+          ? message.result
+          : undefined;
+    if (state) {
+      const runtime = state as RuntimeState;
+      onStatus({
+        storage: storageFromState(runtime),
+        mounts: mountsFromState(runtime),
+      });
+    } else if (message.method === "config.changed" && message.params) {
+      onConfig({ ...DEFAULT_CONFIG, ...(message.params as AppConfig) });
+    }
+  };
+
+  const open = () => {
+    if (stopped || !ksuSpawn) return;
+    let closed = false;
+    const reopen = () => {
+      if (closed) return;
+      closed = true;
+      setTimeout(open, SUBSCRIBE_RETRY_MS);
+    };
+    const child = ksuSpawn(rpcCommand("status.subscribe"));
+    // KernelSU delivers stdout line by line
+    child.stdout.on("data", (data) => {
+      if (stopped) return;
+      data
+        .split("\n")
+        .filter((line) => line.trim())
+        .forEach(handle);
+    });
+    child.on("exit", reopen);
+    child.on("error", reopen);
+  };
+
+  open();
+  return () => {
+    stopped = true;
+  };
+}
+
 interface AppAPI {
   loadConfig: () => Promise<AppConfig>;
   saveConfig: (config: AppConfig) => Promise<void>;
@@ -87,26 +261,29 @@ interface AppAPI {
   createSilo: (reason: string) => Promise<void>;
   deleteSilo: (siloId: string) => Promise<void>;
   restoreSilo: (siloId: string) => Promise<void>;
+  getRescueReport: () => Promise<RescueReport>;
   readLogs: () => Promise<string>;
+  subscribeStatus: (
+    onStatus: (update: StatusUpdate) => void,
+    onConfig: (config: AppConfig) => void,
+  ) => () => void;
 }
 
 const RealAPI: AppAPI = {
   loadConfig: async (): Promise<AppConfig> => {
-    if (!ksuExec) return DEFAULT_CONFIG;
-    const cmd = `${PATHS.BINARY} show-config`;
-    try {
-      const { errno, stdout } = await ksuExec(cmd);
-      if (errno === 0 && stdout) {
-        const loaded = JSON.parse(stdout);
-        return { ...DEFAULT_CONFIG, ...loaded };
-      }
-    } catch {
-      // ignore
-    }
-    return DEFAULT_CONFIG;
+    const loaded = await query<AppConfig>(
+      "config.get",
+      `${PATHS.BINARY} show-config`,
+    );
+    return loaded ? { ...DEFAULT_CONFIG, ...loaded } : DEFAULT_CONFIG;
   },
   saveConfig: async (config: AppConfig): Promise<void> => {
     if (!ksuExec) throw new Error("No KSU environment");
+    try {
+      if (await rpc<boolean>("config.set", { config })) return;
+    } catch (e) {
+      throw new Error(`Failed to save config: ${(e as Error).message}`);
+    }
     const jsonStr = JSON.stringify(config);
     const hexPayload = stringToHex(jsonStr);
     const cmd = `${PATHS.BINARY} save-config --payload ${hexPayload}`;
@@ -121,15 +298,9 @@ const RealAPI: AppAPI = {
   },
   // eslint-disable-next-line @typescript-eslint/no-unused-vars
   scanModules: async (_path?: string): Promise<Module[]> => {
-    if (!ksuExec) return [];
-    const cmd = `${PATHS.BINARY} modules`;
-    try {
-      const { errno, stdout } = await ksuExec(cmd);
-      if (errno === 0 && stdout) return JSON.parse(stdout);
-    } catch {
-      // ignore
-    }
-    return [];
+    return (
+      (await query<Module[]>("modules.list", `${PATHS.BINARY} modules`)) ?? []
+    );
   },
   saveModules: async (_modules: Module[]): Promise<void> => {
     return;
@@ -145,12 +316,17 @@ const RealAPI: AppAPI = {
     return "";
   },
 
-  // Reverted to standard command execution
   saveModuleRules: async (
     moduleId: string,
     rules: ModuleRules,
   ): Promise<void> => {
     if (!ksuExec) throw new Error("No KSU environment");
+    try {
+      if (await rpc<boolean>("modules.save_rules", { module: moduleId, rules }))
+        return;
+    } catch (e) {
+      throw new Error(`Failed to save rules: ${(e as Error).message}`);
+    }
     const jsonStr = JSON.stringify(rules);
     const hexPayload = stringToHex(jsonStr);
     const cmd = `${PATHS.BINARY} save-module-rules --module "${moduleId}" --payload ${hexPayload}`;
@@ -158,24 +334,8 @@ const RealAPI: AppAPI = {
     if (errno !== 0) throw new Error(`Failed to save rules: ${stderr}`);
   },
   getStorageUsage: async (): Promise<StorageStatus> => {
-    if (!ksuExec) return { size: "-", used: "-", percent: "0%", type: null };
-    try {
-      const stateFile =
-        (PATHS as Record<string, string>).DAEMON_STATE ||
-        "/data/adb/meta-hybrid/run/daemon_state.json";
-      const { errno, stdout } = await ksuExec(`cat "${stateFile}"`);
-      if (errno === 0 && stdout) {
-        const state = JSON.parse(stdout);
-        return {
-          type: state.storage_mode || "unknown",
-          percent: `${state.storage_percent ?? 0}%`,
-          size: formatBytes(state.storage_total ?? 0),
-          used: formatBytes(state.storage_used ?? 0),
-        };
-      }
-    } catch {
-      // ignore
-    }
+    const state = await loadRuntimeState();
+    if (state) return storageFromState(state);
     return { size: "-", used: "-", percent: "0%", type: null };
   },
   getSystemInfo: async (): Promise<SystemInfo> => {
@@ -198,24 +358,8 @@ const RealAPI: AppAPI = {
             info.selinux = line.substring(8).trim();
         });
       }
-      const stateFile =
-        (PATHS as Record<string, string>).DAEMON_STATE ||
-        "/data/adb/meta-hybrid/run/daemon_state.json";
-      const { errno: errState, stdout: outState } = await ksuExec(
-        `cat "${stateFile}"`,
-      );
-      if (errState === 0 && outState) {
-        try {
-          const state = JSON.parse(outState);
-          info.mountBase = state.mount_point || "Unknown";
-          info.activeMounts = state.active_mounts || [];
-          if (state.zygisksu_enforce !== undefined) {
-            info.zygisksuEnforce = state.zygisksu_enforce ? "1" : "0";
-          }
-        } catch {
-          // ignore
-        }
-      }
+      const state = await loadRuntimeState();
+      if (state) Object.assign(info, mountsFromState(state));
       return info;
     } catch {
       return { kernel: "-", selinux: "-", mountBase: "-", activeMounts: [] };
@@ -292,59 +436,63 @@ const RealAPI: AppAPI = {
     return null;
   },
   getConflicts: async (): Promise<ConflictEntry[]> => {
-    if (!ksuExec) return [];
-    try {
-      const { errno, stdout } = await ksuExec(`${PATHS.BINARY} conflicts`);
-      if (errno === 0 && stdout) return JSON.parse(stdout);
-    } catch {
-      // ignore
-    }
-    return [];
+    return (
+      (await query<ConflictEntry[]>(
+        "conflicts",
+        `${PATHS.BINARY} conflicts`,
+      )) ?? []
+    );
   },
   getDiagnostics: async (): Promise<DiagnosticIssue[]> => {
-    if (!ksuExec) return [];
-    try {
-      const { errno, stdout } = await ksuExec(`${PATHS.BINARY} diagnostics`);
-      if (errno === 0 && stdout) return JSON.parse(stdout);
-    } catch {
-      // ignore
-    }
-    return [];
+    return (
+      (await query<DiagnosticIssue[]>(
+        "diagnostics",
+        `${PATHS.BINARY} diagnostics`,
+      )) ?? []
+    );
   },
   reboot: async (): Promise<void> => {
     if (!ksuExec) return;
     await ksuExec("reboot");
   },
   getGranaryList: async (): Promise<Silo[]> => {
-    if (!ksuExec) return [];
-    try {
-      const { errno, stdout } = await ksuExec(
+    return (
+      (await query<Silo[]>(
+        "granary.list",
         `${PATHS.BINARY} system-action --action granary-list`,
-      );
-      if (errno === 0 && stdout) return JSON.parse(stdout);
-    } catch {
-      // ignore
-    }
-    return [];
+      )) ?? []
+    );
   },
   createSilo: async (reason: string): Promise<void> => {
-    if (!ksuExec) return;
-    const cmd = `${PATHS.BINARY} system-action --action granary-create --value "${reason}"`;
-    const { errno, stderr } = await ksuExec(cmd);
-    if (errno !== 0) throw new Error(stderr);
+    await command(
+      "granary.create",
+      { reason },
+      `${PATHS.BINARY} system-action --action granary-create --value "${reason}"`,
+    );
   },
   deleteSilo: async (siloId: string): Promise<void> => {
-    if (!ksuExec) return;
-    const cmd = `${PATHS.BINARY} system-action --action granary-delete --value "${siloId}"`;
-    const { errno, stderr } = await ksuExec(cmd);
-    if (errno !== 0) throw new Error(stderr);
+    await command(
+      "granary.delete",
+      { id: siloId },
+      `${PATHS.BINARY} system-action --action granary-delete --value "${siloId}"`,
+    );
   },
   restoreSilo: async (siloId: string): Promise<void> => {
-    if (!ksuExec) return;
-    const cmd = `${PATHS.BINARY} system-action --action granary-restore --value "${siloId}"`;
-    const { errno, stderr } = await ksuExec(cmd);
-    if (errno !== 0) throw new Error(stderr);
+    await command(
+      "granary.restore",
+      { id: siloId },
+      `${PATHS.BINARY} system-action --action granary-restore --value "${siloId}"`,
+    );
+  },
+  getRescueReport: async (): Promise<RescueReport> => {
+    return (
+      (await query<RescueReport>("rescue", `${PATHS.BINARY} rescue`)) ?? {
+        session: null,
+        journal: [],
+      }
+    );
   },
+  subscribeStatus,
 };
 
 export const API: AppAPI = shouldUseMock
@@ -206,6 +206,17 @@ const createGlobalStore = () => {
     } catch {}
 
     await Promise.all([loadConfig(), loadStatus()]);
+
+    API.subscribeStatus(
+      ({ storage, mounts }) => {
+        setStorage(storage);
+        setSystemInfo((info) => ({ ...info, ...mounts }));
+        setActivePartitions(mounts.activeMounts);
+      },
+      (changed) => {
+        if (!savingConfig()) setConfig(changed);
+      },
+    );
   }
 
   async function loadConfig() {
@@ -11,6 +11,10 @@ export interface BackupConfig {
 export interface ModuleRules {
   default_mode: MountMode;
   paths: Record<string, string>;
+  priority?: number;
+  requires?: string[];
+  conflicts?: string[];
+  provides?: string[];
 }
 
 export type OverlayMode = "tmpfs" | "ext4" | "erofs";
@@ -41,6 +45,7 @@ export interface Module {
   enabled?: boolean;
   source_path?: string;
   rules: ModuleRules;
+  excluded?: string;
 }
 
 export interface StorageStatus {
@@ -61,6 +66,22 @@ export interface SystemInfo {
   supported_overlay_modes?: OverlayMode[];
 }
 
+/** daemon_state.json, also returned by the daemon's `status` method. */
+export interface RuntimeState {
+  storage_mode?: string;
+  mount_point?: string;
+  active_mounts?: string[];
+  storage_total?: number;
+  storage_used?: number;
+  storage_percent?: number;
+  zygisksu_enforce?: boolean;
+}
+
+export interface StatusUpdate {
+  storage: StorageStatus;
+  mounts: Pick<SystemInfo, "mountBase" | "activeMounts" | "zygisksuEnforce">;
+}
+
 export interface DeviceInfo {
   model: string;
   android: string;
@@ -90,6 +111,7 @@ export interface ConflictEntry {
   partition: string;
   relative_path: string;
   contending_modules: string[];
+  winner: string;
 }
 
 export interface Silo {
@@ -102,6 +124,34 @@ export interface Silo {
   raw_state?: string;
 }
 
+export interface RescueSession {
+  started_at: number;
+  step: number;
+  suspects: string[];
+  disabled: string[];
+  cleared: string[];
+}
+
+export interface RescueJournalEntry {
+  timestamp: number;
+  step: number;
+  event:
+    | "started"
+    | "disabled"
+    | "boot_failed"
+    | "boot_succeeded"
+    | "verdict"
+    | "inconclusive"
+    | "aborted";
+  modules: string[];
+  message: string;
+}
+
+export interface RescueReport {
+  session: RescueSession | null;
+  journal: RescueJournalEntry[];
+}
+
 export interface DiagnosticIssue {
   level: "Info" | "Warning" | "Critical";
   context: string;
@@ -6,7 +6,7 @@
 import { createSignal, createMemo, onMount, Show, For } from "solid-js";
 import { API } from "../lib/api";
 import { store } from "../lib/store";
-import type { Silo } from "../lib/types";
+import type { Silo, RescueReport } from "../lib/types";
 import Skeleton from "../components/Skeleton";
 import BottomActions from "../components/BottomActions";
 import "./GranaryTab.css";
@@ -23,6 +23,10 @@ import "@material/web/textfield/outlined-text-field.js";
 
 export default function GranaryTab() {
   const [silos, setSilos] = createSignal<Silo[]>([]);
+  const [rescue, setRescue] = createSignal<RescueReport>({
+    session: null,
+    journal: [],
+  });
   const [loading, setLoading] = createSignal(true);
   const [busyId, setBusyId] = createSignal<string | null>(null);
 
@@ -51,8 +55,12 @@ export default function GranaryTab() {
   async function loadSilos() {
     setLoading(true);
     try {
-      const list = await API.getGranaryList();
+      const [list, report] = await Promise.all([
+        API.getGranaryList(),
+        API.getRescueReport(),
+      ]);
       setSilos(list);
+      setRescue(report);
     } catch {
       store.showToast(L_G().loadError || "Failed to load Granary", "error");
     } finally {
@@ -226,6 +234,41 @@ export default function GranaryTab() {
       </md-dialog>
 
       <div class="granary-list">
+        <Show when={!loading() && rescue().journal.length > 0}>
+          <div class="silo-card rescue-card">
+            <div class="silo-title">
+              {L_G().rescueTitle || "Rescue Journal"}
+            </div>
+            <Show when={rescue().session}>
+              {(session) => (
+                <div class="silo-meta-row">
+                  <span class="reason-badge">
+                    {(L_G().rescueActive || "Bisecting, step {step}").replace(
+                      "{step}",
+                      String(session().step),
+                    )}
+                  </span>
+                  <span class="time-text">
+                    {session().suspects.join(", ")}
+                  </span>
+                </div>
+              )}
+            </Show>
+            <For each={rescue().journal.slice(-10).reverse()}>
+              {(entry) => (
+                <div class="rescue-entry">
+                  <div class="silo-meta-row">
+                    <span class="reason-badge">{entry.event}</span>
+                    <span class="time-text">
+                      {formatTime(entry.timestamp)}
+                    </span>
+                  </div>
+                  <div class="rescue-message">{entry.message}</div>
+                </div>
+              )}
+            </For>
+          </div>
+        </Show>
         <Show
           when={!loading()}
           fallback={